@@ -1,33 +1,98 @@
 #[derive(Clone, Debug)]
-pub enum Expr {
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+impl Expr {
+    pub fn new(kind: ExprKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum ExprKind {
     Number(i64),
+    Float(f64),
     Str(String),
     Bool(bool),
     Var(String),
-    Call { name: String, args: Vec<Expr> },
+    Call {
+        name: String,
+        args: Vec<Expr>,
+    },
+    Apply {
+        callee: Box<Expr>,
+        args: Vec<Expr>,
+    },
+    Lambda {
+        params: Vec<(String, Option<Expr>)>,
+        body: Vec<Node>,
+    },
+    List(Vec<Expr>),
+    Map(Vec<(Expr, Expr)>),
+    Index(Box<Expr>, Box<Expr>),
+    // value.name, a struct field or a variable of an imported file
+    Field(Box<Expr>, String),
     Unary(Op, Box<Expr>),
     Binary(Box<Expr>, Op, Box<Expr>),
+    // cond ? then : otherwise
+    Cond(Box<Expr>, Box<Expr>, Box<Expr>),
+    // a condition under `use truthy`, turns any value into a Bool
+    Truthy(Box<Expr>),
+    // `async { ... }` used as a value, gives the task it started
+    Async(Vec<Node>),
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Span {
+    pub line: usize,
+    pub col: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct Node {
+    pub kind: NodeKind,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug)]
+pub enum AssignTarget {
+    Var(String),
+    // list[index] or map[key]
+    Index(Expr, Expr),
+    // value.field
+    Field(Expr, String),
 }
 
 #[derive(Clone, Debug)]
-pub enum Node {
+pub enum NodeKind {
     VarDecl {
         name: String,
         value: Expr,
+        constant: bool,
     },
+    // `x = v`, or `x += v` and friends with op set; `x++` is `x += 1`
     Assign {
-        name: String,
+        target: AssignTarget,
+        op: Option<Op>,
         value: Expr,
     },
     Call {
         name: String,
         args: Vec<Expr>,
     },
+    Expr(Expr),
     Function {
         name: String,
         params: Vec<(String, Option<Expr>)>,
         body: Vec<Node>,
     },
+    // struct Name { field, ... }
+    Struct {
+        name: String,
+        fields: Vec<String>,
+    },
     Return(Option<Expr>),
     Loop {
         times: Expr,
@@ -37,6 +102,11 @@ pub enum Node {
         cond: Expr,
         body: Vec<Node>,
     },
+    For {
+        var: String,
+        iter: Expr,
+        body: Vec<Node>,
+    },
     If {
         cond: Expr,
         then_body: Vec<Node>,
@@ -50,16 +120,64 @@ pub enum Node {
         negated: bool,
         body: Vec<Node>,
     },
+    Try {
+        body: Vec<Node>,
+        catch: Option<(Option<String>, Vec<Node>)>,
+        finally: Option<Vec<Node>>,
+    },
+    Match {
+        subject: Expr,
+        arms: Vec<(Vec<Pattern>, Vec<Node>)>,
+    },
+    Select {
+        arms: Vec<(SelectArm, Vec<Node>)>,
+    },
+    // only one task at a time runs a `lock` block with the same name
+    Lock {
+        name: String,
+        body: Vec<Node>,
+    },
+    // a file setting like `use truthy`, applied while parsing
+    Use,
+    // `import "path" as alias`, the file itself is loaded before the script runs
+    Import {
+        path: String,
+        alias: String,
+    },
     Break,
     Continue,
 }
 
+// what a `select` arm waits for
+#[derive(Clone, Debug)]
+pub enum SelectArm {
+    // recv(rx) as msg
+    Recv { from: Expr, var: Option<String> },
+    Key(Expr),
+    // milliseconds from when the select starts
+    Timeout(Expr),
+}
+
+#[derive(Clone, Debug)]
+pub enum Pattern {
+    Value(Expr),
+    // start..end leaves out end, start..=end includes it
+    Range {
+        start: Expr,
+        end: Expr,
+        inclusive: bool,
+    },
+    Wildcard,
+}
+
 #[derive(Clone, Copy, Debug)]
 pub enum Op {
     Add,
     Sub,
     Mul,
     Div,
+    Mod,
+    Pow,
     Eq,
     Ne,
     Gt,
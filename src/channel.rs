@@ -0,0 +1,100 @@
+use crate::interpreter::Value;
+use crate::task::{self, Waker};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// The queue behind the sender and receiver that `channel()` returns. Values
+// come out in the order they were sent; sending never blocks.
+#[derive(Debug)]
+pub struct Channel {
+    queue: Mutex<Queue>,
+}
+
+#[derive(Debug, Default)]
+struct Queue {
+    items: VecDeque<Value>,
+    closed: bool,
+    // tasks waiting in recv or select, woken by the next send or close
+    waiting: Vec<Waker>,
+}
+
+pub enum Recv {
+    Value(Value),
+    // nothing arrived before the timeout
+    Empty,
+    // closed and everything sent has been received
+    Closed,
+    Cancelled,
+}
+
+impl Channel {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            queue: Mutex::new(Queue::default()),
+        })
+    }
+
+    pub fn send(&self, v: Value) -> Result<(), String> {
+        let mut queue = self.queue.lock().unwrap();
+        if queue.closed {
+            return Err("send on a closed channel".into());
+        }
+        queue.items.push_back(v);
+        let waiting = std::mem::take(&mut queue.waiting);
+        drop(queue);
+        for w in waiting {
+            w.wake();
+        }
+        Ok(())
+    }
+
+    // values already sent can still be received afterwards
+    pub fn close(&self) {
+        let mut queue = self.queue.lock().unwrap();
+        queue.closed = true;
+        let waiting = std::mem::take(&mut queue.waiting);
+        drop(queue);
+        for w in waiting {
+            w.wake();
+        }
+    }
+
+    // wakes w at the next send or close, for select
+    pub fn watch(&self, w: Waker) {
+        w.add_to(&mut self.queue.lock().unwrap().waiting);
+    }
+
+    pub fn try_recv(&self) -> Recv {
+        let mut queue = self.queue.lock().unwrap();
+        match queue.items.pop_front() {
+            Some(v) => Recv::Value(v),
+            None if queue.closed => Recv::Closed,
+            None => Recv::Empty,
+        }
+    }
+
+    // waits for a value, forever without a timeout; a cancelled task stops waiting
+    pub fn recv(&self, timeout: Option<Duration>) -> Recv {
+        let deadline = timeout.and_then(task::deadline);
+        loop {
+            {
+                let mut queue = self.queue.lock().unwrap();
+                if let Some(v) = queue.items.pop_front() {
+                    return Recv::Value(v);
+                }
+                if queue.closed {
+                    return Recv::Closed;
+                }
+                if task::cancelled() {
+                    return Recv::Cancelled;
+                }
+                if deadline.is_some_and(|d| Instant::now() >= d) {
+                    return Recv::Empty;
+                }
+                task::waker().add_to(&mut queue.waiting);
+            }
+            task::wait(deadline);
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use crate::ast::Span;
+
+// Renders the location of a span and the source line it points at:
+//
+//  --> macro.num:3:9
+//   |
+// 3 | let x = foo(
+//   |         ^
+pub fn snippet(file: &str, src: &str, span: Span) -> String {
+    let gutter = " ".repeat(span.line.to_string().len());
+    let mut out = format!("{}--> {}:{}:{}\n", gutter, file, span.line, span.col);
+
+    let line = match src.lines().nth(span.line.saturating_sub(1)) {
+        Some(l) => l,
+        None => return out,
+    };
+
+    // keep tabs so the caret lines up with the source as the terminal shows it
+    let pad: String = line
+        .chars()
+        .take(span.col.saturating_sub(1))
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+
+    out.push_str(&format!("{} |\n", gutter));
+    out.push_str(&format!("{} | {}\n", span.line, line));
+    out.push_str(&format!("{} | {}^\n", gutter, pad));
+    out
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+// A problem found before the script runs. Codes are stable so they can be
+// searched for; the hint is an optional suggestion printed under the snippet.
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub code: &'static str,
+    pub severity: Severity,
+    pub message: String,
+    pub span: Span,
+    pub hint: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn error(code: &'static str, message: impl Into<String>, span: Span) -> Self {
+        Self {
+            code,
+            severity: Severity::Error,
+            message: message.into(),
+            span,
+            hint: None,
+        }
+    }
+
+    pub fn warning(code: &'static str, message: impl Into<String>, span: Span) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::error(code, message, span)
+        }
+    }
+
+    pub fn with_hint(mut self, hint: impl Into<String>) -> Self {
+        self.hint = Some(hint.into());
+        self
+    }
+
+    // error[E002]: expected `)`, found `{`
+    //  --> macro.num:3:9
+    //   ...
+    //   = hint: ...
+    pub fn render(&self, file: &str, src: &str) -> String {
+        let label = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        let mut out = format!("{}[{}]: {}\n", label, self.code, self.message);
+        out.push_str(&snippet(file, src, self.span));
+        if let Some(hint) = &self.hint {
+            let gutter = " ".repeat(self.span.line.to_string().len());
+            out.push_str(&format!("{} = hint: {}\n", gutter, hint));
+        }
+        out
+    }
+}
+
+// The diagnostics of one file, errors first, then warnings, each in source order.
+pub fn render_file(diagnostics: &[Diagnostic], file: &str, src: &str) -> String {
+    let mut sorted: Vec<&Diagnostic> = diagnostics.iter().collect();
+    sorted.sort_by_key(|d| (d.severity == Severity::Warning, d.span.line, d.span.col));
+
+    let mut out = String::new();
+    for d in &sorted {
+        out.push_str(&d.render(file, src));
+        out.push('\n');
+    }
+    out
+}
+
+// The line printed after the diagnostics of all files.
+pub fn summary(diagnostics: &[Diagnostic]) -> String {
+    let errors = diagnostics
+        .iter()
+        .filter(|d| d.severity == Severity::Error)
+        .count();
+    let warnings = diagnostics.len() - errors;
+    let plural = |n: usize, word: &str| format!("{} {}{}", n, word, if n == 1 { "" } else { "s" });
+    let mut out = String::new();
+    match (errors, warnings) {
+        (0, 0) => {}
+        (0, w) => out.push_str(&format!("{} emitted\n", plural(w, "warning"))),
+        (e, 0) => out.push_str(&format!("aborting due to {}\n", plural(e, "error"))),
+        (e, w) => out.push_str(&format!(
+            "aborting due to {}; {} emitted\n",
+            plural(e, "error"),
+            plural(w, "warning")
+        )),
+    }
+    out
+}
+
+// Suggests a keyword for an identifier that looks like a misspelled or
+// borrowed one, e.g. `elsif` -> `elif`, `whlie` -> `while`, `var` -> `let`.
+pub fn keyword_hint(word: &str) -> Option<String> {
+    const KEYWORDS: &[&str] = &[
+        "let", "const", "def", "fn", "return", "if", "elif", "else", "loop", "while", "for", "in",
+        "async", "await", "try", "catch", "finally", "break", "continue", "match", "import",
+        "struct", "select", "lock",
+    ];
+    const ALIASES: &[(&str, &str)] = &[
+        ("elseif", "elif"),
+        ("elsif", "elif"),
+        ("function", "def"),
+        ("func", "def"),
+        ("var", "let"),
+        ("foreach", "for"),
+        ("repeat", "loop"),
+        ("switch", "match"),
+    ];
+
+    let word = word.to_lowercase();
+    if let Some((_, kw)) = ALIASES.iter().find(|(a, _)| *a == word) {
+        return Some(format!("did you mean `{}`?", kw));
+    }
+    // short words are too easy to confuse with ordinary names
+    let limit = if word.chars().count() >= 5 { 2 } else { 1 };
+    KEYWORDS
+        .iter()
+        .filter(|kw| kw.len() > 2 && **kw != word)
+        .map(|kw| (edit_distance(&word, kw), kw))
+        .filter(|(d, _)| *d <= limit)
+        .min_by_key(|(d, _)| *d)
+        .map(|(_, kw)| format!("did you mean `{}`?", kw))
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            cur.push((prev[j] + cost).min(prev[j + 1] + 1).min(cur[j] + 1));
+        }
+        prev = cur;
+    }
+    prev[b.len()]
+}
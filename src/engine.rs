@@ -1,21 +1,87 @@
-use crate::{ast::Node, interpreter::Context};
+use crate::{
+    functions::{exit, release},
+    interpreter::{Context, Error},
+    kill_switch,
+    module::Module,
+    task,
+};
 use std::collections::HashMap;
+use std::sync::Arc;
+use std::thread;
 
 pub struct Engine {
     ctx: Context,
+    modules: Arc<Vec<Module>>,
 }
 
 impl Engine {
-    pub fn new() -> Self {
+    pub fn new(modules: Arc<Vec<Module>>) -> Self {
         let mut builtins = HashMap::new();
         crate::functions::register_all(&mut builtins);
         Self {
-            ctx: Context::new(builtins),
+            ctx: Context::new(builtins, modules.clone()),
+            modules,
         }
     }
 
-    pub fn run(&self, nodes: Vec<Node>) -> Result<(), String> {
-        self.ctx.run(&nodes)?;
-        self.ctx.join_tasks()
+    // Runs the main file, imported files run when their import is reached.
+    // However the script ends, its tasks are waited for, the on_exit
+    // handlers run and every key still held by press is released.
+    pub fn run(&self) -> Result<(), Error> {
+        task::set_main(thread::current());
+        kill_switch::start();
+
+        let result = self.ctx.run(&self.modules[0].ast);
+        // an error in the main script stops the tasks too
+        if result.as_ref().is_err_and(|e| !e.cancelled) {
+            task::stop();
+        }
+        // shielded so a stopped script still waits for the finally blocks
+        let joined = task::shielded(|| self.ctx.join_tasks());
+        release::release_all();
+        let cleanup = self.ctx.run_exit_handlers(exit::handlers());
+        release::release_all();
+
+        match result {
+            Err(e) if !e.cancelled => Err(e),
+            _ => joined.and(cleanup),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    #[test]
+    fn on_exit_handlers_run_last_first_after_the_tasks() {
+        // the handler added first runs last and throws what it saw, as the
+        // error is all that gets out of a finished script
+        let src = r#"
+            let log = []
+            on_exit(fn() { throw(log) })
+            on_exit(fn() { push(log, "second handler") })
+            on_exit(fn() { push(log, "third handler") })
+            async { sleep(5); push(log, "task") }
+            push(log, "main")
+        "#;
+        let (tokens, _) = Lexer::new(src).tokenize();
+        let (ast, diagnostics) = Parser::new(tokens).parse();
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        let modules = Arc::new(vec![Module {
+            file: "test.num".into(),
+            src: src.into(),
+            ast,
+            imports: HashMap::new(),
+        }]);
+
+        let e = Engine::new(modules).run().unwrap_err();
+        assert_eq!(
+            e.message(),
+            r#"["main", "task", "third handler", "second handler"]"#
+        );
+        assert!(!task::stopping());
     }
 }
@@ -14,6 +14,7 @@ fn abs(args: Vec<Value>) -> Value {
 
     match &args[0] {
         Value::Num(n) => Value::Num(n.abs()),
+        Value::Float(n) => Value::Float(n.abs()),
         _ => Value::Error("abs expects a number".into()),
     }
 }
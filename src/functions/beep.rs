@@ -16,10 +16,7 @@ fn beep(args: Vec<Value>) -> Value {
         return e;
     }
 
-    let pitch = match args.get(0) {
-        Some(Value::Num(n)) => *n,
-        _ => 440,
-    };
+    let pitch = args.get(0).and_then(Value::as_i64).unwrap_or(440);
 
     let freq = pitch.clamp(37, 32767) as u32;
 
@@ -0,0 +1,144 @@
+use super::BuiltinFn;
+use crate::channel::{Channel, Recv};
+use crate::functions::{expect_arity, millis};
+use crate::interpreter::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+// Tasks hand values to each other through channels instead of globals.
+// `channel()` gives [sender, receiver], both can be passed to any task.
+pub fn register(map: &mut HashMap<String, BuiltinFn>) {
+    map.insert("channel".into(), channel);
+    map.insert("send".into(), send);
+    map.insert("recv".into(), recv);
+    map.insert("recv_timeout".into(), recv_timeout);
+    map.insert("try_recv".into(), try_recv);
+    map.insert("close".into(), close);
+}
+
+fn receiver<'a>(name: &str, v: &'a Value) -> Result<&'a Arc<Channel>, Value> {
+    match v {
+        Value::Receiver(c) => Ok(c),
+        _ => Err(Value::Error(format!("{} expects a receiver", name))),
+    }
+}
+
+fn sender<'a>(name: &str, v: &'a Value) -> Result<&'a Arc<Channel>, Value> {
+    match v {
+        Value::Sender(c) => Ok(c),
+        _ => Err(Value::Error(format!("{} expects a sender", name))),
+    }
+}
+
+fn channel(_: Vec<Value>) -> Value {
+    let c = Channel::new();
+    Value::list(vec![Value::Sender(c.clone()), Value::Receiver(c)])
+}
+
+fn send(args: Vec<Value>) -> Value {
+    if let Err(e) = expect_arity("send", &args, 2) {
+        return e;
+    }
+
+    let c = match sender("send", &args[0]) {
+        Ok(c) => c,
+        Err(e) => return e,
+    };
+    match c.send(args[1].clone()) {
+        Ok(()) => Value::Bool(false),
+        Err(e) => Value::Error(e),
+    }
+}
+
+// what a receive that found nothing gives back: the optional default, or false
+fn received(name: &str, r: Recv, default: Option<&Value>) -> Value {
+    match r {
+        Recv::Value(v) => v,
+        Recv::Closed => Value::Error(format!("{} on a closed channel", name)),
+        Recv::Empty | Recv::Cancelled => default.cloned().unwrap_or(Value::Bool(false)),
+    }
+}
+
+// waits until a value arrives
+fn recv(args: Vec<Value>) -> Value {
+    if let Err(e) = expect_arity("recv", &args, 1) {
+        return e;
+    }
+
+    match receiver("recv", &args[0]) {
+        Ok(c) => received("recv", c.recv(None), None),
+        Err(e) => e,
+    }
+}
+
+// recv_timeout(receiver, ms) or recv_timeout(receiver, ms, default)
+fn recv_timeout(args: Vec<Value>) -> Value {
+    if let Err(e) = expect_arity("recv_timeout", &args, 2) {
+        return e;
+    }
+
+    let c = match receiver("recv_timeout", &args[0]) {
+        Ok(c) => c,
+        Err(e) => return e,
+    };
+    let timeout = match args[1].as_f64() {
+        Some(ms) if ms >= 0.0 => millis("recv_timeout", ms),
+        _ => return Value::Error("recv_timeout expects a number of milliseconds".into()),
+    };
+    let r = match timeout {
+        Ok(t) => c.recv(Some(t)),
+        Err(e) => return Value::Error(e),
+    };
+    received("recv_timeout", r, args.get(2))
+}
+
+// try_recv(receiver) or try_recv(receiver, default), never waits
+fn try_recv(args: Vec<Value>) -> Value {
+    if let Err(e) = expect_arity("try_recv", &args, 1) {
+        return e;
+    }
+
+    match receiver("try_recv", &args[0]) {
+        Ok(c) => received("try_recv", c.try_recv(), args.get(1)),
+        Err(e) => e,
+    }
+}
+
+// no more sends; receivers get what is left, then an error
+fn close(args: Vec<Value>) -> Value {
+    if let Err(e) = expect_arity("close", &args, 1) {
+        return e;
+    }
+
+    match sender("close", &args[0]) {
+        Ok(c) => {
+            c.close();
+            Value::Bool(false)
+        }
+        Err(e) => e,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recv_timeout_rejects_times_that_are_not_finite_or_too_long() {
+        let Value::List(ends) = channel(Vec::new()) else {
+            panic!("channel() should give a list");
+        };
+        let rx = ends.lock().unwrap()[1].clone();
+        for ms in [1e25, 10f64.powi(400), f64::INFINITY, f64::NAN] {
+            let v = recv_timeout(vec![rx.clone(), Value::Float(ms)]);
+            assert!(matches!(v, Value::Error(_)));
+        }
+    }
+
+    #[test]
+    fn recv_timeout_gives_the_default_when_nothing_arrives() {
+        let c = Channel::new();
+        let v = recv_timeout(vec![Value::Receiver(c), Value::Float(1.5), Value::Num(7)]);
+        assert!(matches!(v, Value::Num(7)));
+    }
+}
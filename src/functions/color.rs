@@ -71,18 +71,18 @@ fn color(args: Vec<Value>) -> Value {
         None => return Value::Error("invalid hex color".into()),
     };
 
-    let x = match args.get(1) {
-        Some(Value::Num(n)) if *n >= 0 => *n as usize,
+    let x = match args.get(1).and_then(Value::as_i64) {
+        Some(n) if n >= 0 => n as usize,
         _ => return Value::Error("color expects number x".into()),
     };
 
-    let y = match args.get(2) {
-        Some(Value::Num(n)) if *n >= 0 => *n as usize,
+    let y = match args.get(2).and_then(Value::as_i64) {
+        Some(n) if n >= 0 => n as usize,
         _ => return Value::Error("color expects number y".into()),
     };
 
-    let tol = match args.get(3) {
-        Some(Value::Num(n)) if *n >= 0 => *n,
+    let tol = match args.get(3).and_then(Value::as_i64) {
+        Some(n) if n >= 0 => n,
         _ => return Value::Error("color expects tolerance".into()),
     };
 
@@ -0,0 +1,158 @@
+use super::BuiltinFn;
+use crate::functions::expect_arity;
+use crate::interpreter::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicI64, Ordering};
+
+// A counter is a number that tasks can change at the same time without
+// losing updates and without a `lock` block.
+pub fn register(map: &mut HashMap<String, BuiltinFn>) {
+    map.insert("counter".into(), counter);
+    map.insert("incr".into(), incr);
+    map.insert("decr".into(), decr);
+    map.insert("load".into(), load);
+    map.insert("store".into(), store);
+}
+
+fn get<'a>(name: &str, v: &'a Value) -> Result<&'a Arc<AtomicI64>, Value> {
+    match v {
+        Value::Counter(c) => Ok(c),
+        _ => Err(Value::Error(format!("{} expects a counter", name))),
+    }
+}
+
+// the optional number argument at i, or the default
+fn number(name: &str, args: &[Value], i: usize, default: i64) -> Result<i64, Value> {
+    match args.get(i) {
+        None => Ok(default),
+        Some(v) => v
+            .as_i64()
+            .ok_or_else(|| Value::Error(format!("{} expects an integer", name))),
+    }
+}
+
+// counter() starts at 0, counter(n) at n
+fn counter(args: Vec<Value>) -> Value {
+    match number("counter", &args, 0, 0) {
+        Ok(n) => Value::Counter(Arc::new(AtomicI64::new(n))),
+        Err(e) => e,
+    }
+}
+
+// incr(c) or incr(c, n), returns the new value
+fn incr(args: Vec<Value>) -> Value {
+    add("incr", args, 1)
+}
+
+// decr(c) or decr(c, n), returns the new value
+fn decr(args: Vec<Value>) -> Value {
+    add("decr", args, -1)
+}
+
+fn add(name: &str, args: Vec<Value>, sign: i64) -> Value {
+    if let Err(e) = expect_arity(name, &args, 1) {
+        return e;
+    }
+
+    let c = match get(name, &args[0]) {
+        Ok(c) => c,
+        Err(e) => return e,
+    };
+    let n = match number(name, &args, 1, 1) {
+        Ok(n) if sign < 0 => n.checked_neg(),
+        Ok(n) => Some(n),
+        Err(e) => return e,
+    };
+    // overflows like `+` does rather than wrapping around, and leaves the
+    // counter as it was
+    let overflow = || Value::Error("integer overflow".into());
+    let Some(n) = n else {
+        return overflow();
+    };
+    let mut old = c.load(Ordering::SeqCst);
+    loop {
+        let Some(new) = old.checked_add(n) else {
+            return overflow();
+        };
+        match c.compare_exchange_weak(old, new, Ordering::SeqCst, Ordering::SeqCst) {
+            Ok(_) => return Value::Num(new),
+            Err(now) => old = now,
+        }
+    }
+}
+
+fn load(args: Vec<Value>) -> Value {
+    if let Err(e) = expect_arity("load", &args, 1) {
+        return e;
+    }
+
+    match get("load", &args[0]) {
+        Ok(c) => Value::Num(c.load(Ordering::SeqCst)),
+        Err(e) => e,
+    }
+}
+
+// store(c, n) sets the counter, returns the value it had
+fn store(args: Vec<Value>) -> Value {
+    if let Err(e) = expect_arity("store", &args, 2) {
+        return e;
+    }
+
+    let c = match get("store", &args[0]) {
+        Ok(c) => c,
+        Err(e) => return e,
+    };
+    match number("store", &args, 1, 0) {
+        Ok(n) => Value::Num(c.swap(n, Ordering::SeqCst)),
+        Err(e) => e,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_counter(n: i64) -> Value {
+        counter(vec![Value::Num(n)])
+    }
+
+    fn value(c: &Value) -> i64 {
+        match load(vec![c.clone()]) {
+            Value::Num(n) => n,
+            v => panic!("expected a number, got {:?}", v),
+        }
+    }
+
+    #[test]
+    fn incr_past_the_largest_integer_is_an_error() {
+        let c = new_counter(i64::MAX);
+        assert!(matches!(incr(vec![c.clone()]), Value::Error(e) if e == "integer overflow"));
+        assert_eq!(value(&c), i64::MAX);
+    }
+
+    #[test]
+    fn decr_by_the_smallest_integer_is_an_error() {
+        let c = new_counter(0);
+        let v = decr(vec![c.clone(), Value::Num(i64::MIN)]);
+        assert!(matches!(v, Value::Error(e) if e == "integer overflow"));
+        let c = new_counter(i64::MIN);
+        assert!(matches!(decr(vec![c.clone()]), Value::Error(_)));
+        assert_eq!(value(&c), i64::MIN);
+    }
+
+    #[test]
+    fn incr_and_decr_give_the_new_value() {
+        let c = new_counter(5);
+        assert!(matches!(
+            incr(vec![c.clone(), Value::Num(3)]),
+            Value::Num(8)
+        ));
+        assert!(matches!(decr(vec![c.clone()]), Value::Num(7)));
+        assert!(matches!(
+            store(vec![c.clone(), Value::Num(1)]),
+            Value::Num(7)
+        ));
+        assert_eq!(value(&c), 1);
+    }
+}
@@ -1,10 +1,54 @@
+use crate::functions::expect_arity;
 use crate::interpreter::Value;
-use std::process;
+use crate::kill_switch;
+use crate::task;
+use std::sync::Mutex;
+
+// functions given to on_exit, run when the script ends
+static HANDLERS: Mutex<Vec<Value>> = Mutex::new(Vec::new());
 
 pub fn register(map: &mut std::collections::HashMap<String, fn(Vec<Value>) -> Value>) {
     map.insert("exit".into(), exit);
+    map.insert("on_exit".into(), on_exit);
+    map.insert("kill_switch".into(), set_kill_switch);
+}
+
+pub fn handlers() -> Vec<Value> {
+    HANDLERS.lock().unwrap().clone()
 }
 
+// stops every task and the script itself, which then ends like the kill switch
+// was pressed: on_exit handlers run and held keys are released
 fn exit(_args: Vec<Value>) -> Value {
-    process::exit(0);
+    task::stop();
+    Value::Bool(false)
+}
+
+fn on_exit(args: Vec<Value>) -> Value {
+    if let Err(e) = expect_arity("on_exit", &args, 1) {
+        return e;
+    }
+
+    match &args[0] {
+        Value::Func(_) => {
+            HANDLERS.lock().unwrap().push(args[0].clone());
+            Value::Bool(false)
+        }
+        _ => Value::Error("on_exit expects a function".into()),
+    }
+}
+
+// kill_switch(F8), kill_switch("Ctrl+Shift+K") or kill_switch(false) to turn it off
+fn set_kill_switch(args: Vec<Value>) -> Value {
+    if let Err(e) = expect_arity("kill_switch", &args, 1) {
+        return e;
+    }
+
+    match &args[0] {
+        Value::Symbol(s) | Value::Str(s) => kill_switch::set(Some(s.clone())),
+        Value::Num(n) => kill_switch::set(Some(n.to_string())),
+        Value::Bool(false) => kill_switch::set(None),
+        _ => return Value::Error("kill_switch expects a key name or false".into()),
+    }
+    Value::Bool(false)
 }
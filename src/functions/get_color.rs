@@ -50,13 +50,13 @@ fn get_color(args: Vec<Value>) -> Value {
         return e;
     }
 
-    let x = match args.get(0) {
-        Some(Value::Num(n)) if *n >= 0 => *n as usize,
+    let x = match args.get(0).and_then(Value::as_i64) {
+        Some(n) if n >= 0 => n as usize,
         _ => return Value::Error("get_color expects number x".into()),
     };
 
-    let y = match args.get(1) {
-        Some(Value::Num(n)) if *n >= 0 => *n as usize,
+    let y = match args.get(1).and_then(Value::as_i64) {
+        Some(n) if n >= 0 => n as usize,
         _ => return Value::Error("get_color expects number y".into()),
     };
 
@@ -1,4 +1,3 @@
-use crate::functions::expect_arity;
 use crate::interpreter::Value;
 use device_query::{DeviceQuery, DeviceState};
 
@@ -6,9 +5,12 @@ pub fn register(map: &mut std::collections::HashMap<String, fn(Vec<Value>) -> Va
     map.insert("get_mouse".into(), get_mouse);
 }
 
+// get_mouse() gives the whole position as a Point, get_mouse(x) one axis
 fn get_mouse(args: Vec<Value>) -> Value {
-    if let Err(e) = expect_arity("get_mouse", &args, 1) {
-        return e;
+    let device = DeviceState::new();
+    let (x, y) = device.get_mouse().coords;
+    if args.is_empty() {
+        return Value::point(x as i64, y as i64);
     }
 
     let axis = match &args[0] {
@@ -16,9 +18,6 @@ fn get_mouse(args: Vec<Value>) -> Value {
         _ => return Value::Error("get_mouse expects axis name".into()),
     };
 
-    let device = DeviceState::new();
-    let (x, y) = device.get_mouse().coords;
-
     let value = match axis {
         "x" | "hor" | "width" => x as i64,
         "y" | "ver" | "height" => y as i64,
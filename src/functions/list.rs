@@ -0,0 +1,101 @@
+use super::BuiltinFn;
+use crate::functions::expect_arity;
+use crate::interpreter::{Value, list_index, map_key};
+use std::collections::HashMap;
+
+pub fn register(map: &mut HashMap<String, BuiltinFn>) {
+    map.insert("len".into(), len);
+    map.insert("push".into(), push);
+    map.insert("pop".into(), pop);
+    map.insert("insert".into(), insert);
+    map.insert("remove".into(), remove);
+}
+
+fn len(args: Vec<Value>) -> Value {
+    if let Err(e) = expect_arity("len", &args, 1) {
+        return e;
+    }
+
+    match &args[0] {
+        Value::List(items) => Value::Num(items.lock().unwrap().len() as i64),
+        Value::Map(entries) => Value::Num(entries.lock().unwrap().len() as i64),
+        Value::Str(s) => Value::Num(s.chars().count() as i64),
+        _ => Value::Error("len expects a list, map or string".into()),
+    }
+}
+
+fn push(args: Vec<Value>) -> Value {
+    if let Err(e) = expect_arity("push", &args, 2) {
+        return e;
+    }
+
+    match &args[0] {
+        Value::List(items) => {
+            items.lock().unwrap().extend(args[1..].iter().cloned());
+            Value::Bool(false)
+        }
+        _ => Value::Error("push expects a list".into()),
+    }
+}
+
+fn pop(args: Vec<Value>) -> Value {
+    if let Err(e) = expect_arity("pop", &args, 1) {
+        return e;
+    }
+
+    match &args[0] {
+        Value::List(items) => match items.lock().unwrap().pop() {
+            Some(v) => v,
+            None => Value::Error("pop from empty list".into()),
+        },
+        _ => Value::Error("pop expects a list".into()),
+    }
+}
+
+fn insert(args: Vec<Value>) -> Value {
+    if let Err(e) = expect_arity("insert", &args, 3) {
+        return e;
+    }
+
+    let items = match &args[0] {
+        Value::List(items) => items,
+        _ => return Value::Error("insert expects a list".into()),
+    };
+
+    let mut items = items.lock().unwrap();
+    // inserting at the end is allowed
+    match list_index(&args[1], items.len() + 1) {
+        Ok(i) => {
+            items.insert(i, args[2].clone());
+            Value::Bool(false)
+        }
+        Err(e) => Value::Error(e),
+    }
+}
+
+fn remove(args: Vec<Value>) -> Value {
+    if let Err(e) = expect_arity("remove", &args, 2) {
+        return e;
+    }
+
+    match &args[0] {
+        Value::List(items) => {
+            let mut items = items.lock().unwrap();
+            match list_index(&args[1], items.len()) {
+                Ok(i) => items.remove(i),
+                Err(e) => Value::Error(e),
+            }
+        }
+        Value::Map(entries) => {
+            let key = match map_key(&args[1]) {
+                Ok(k) => k,
+                Err(e) => return Value::Error(e),
+            };
+            match entries.lock().unwrap().remove(&key) {
+                Some(v) => v,
+                None => Value::Error(format!("key \"{}\" not found in map", key)),
+            }
+        }
+        _ => Value::Error("remove expects a list or map".into()),
+    }
+}
@@ -0,0 +1,60 @@
+use super::BuiltinFn;
+use crate::functions::expect_arity;
+use crate::interpreter::{Value, map_key};
+use std::collections::HashMap;
+
+pub fn register(map: &mut HashMap<String, BuiltinFn>) {
+    map.insert("keys".into(), keys);
+    map.insert("values".into(), values);
+    map.insert("has".into(), has);
+}
+
+fn keys(args: Vec<Value>) -> Value {
+    if let Err(e) = expect_arity("keys", &args, 1) {
+        return e;
+    }
+
+    match &args[0] {
+        Value::Map(entries) => {
+            let keys = entries
+                .lock()
+                .unwrap()
+                .keys()
+                .cloned()
+                .map(Value::Str)
+                .collect();
+            Value::list(keys)
+        }
+        _ => Value::Error("keys expects a map".into()),
+    }
+}
+
+fn values(args: Vec<Value>) -> Value {
+    if let Err(e) = expect_arity("values", &args, 1) {
+        return e;
+    }
+
+    match &args[0] {
+        Value::Map(entries) => {
+            let values = entries.lock().unwrap().values().cloned().collect();
+            Value::list(values)
+        }
+        _ => Value::Error("values expects a map".into()),
+    }
+}
+
+fn has(args: Vec<Value>) -> Value {
+    if let Err(e) = expect_arity("has", &args, 2) {
+        return e;
+    }
+
+    let entries = match &args[0] {
+        Value::Map(entries) => entries,
+        _ => return Value::Error("has expects a map".into()),
+    };
+
+    match map_key(&args[1]) {
+        Ok(key) => Value::Bool(entries.lock().unwrap().contains_key(&key)),
+        Err(e) => Value::Error(e),
+    }
+}
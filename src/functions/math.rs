@@ -0,0 +1,254 @@
+use super::BuiltinFn;
+use crate::functions::expect_arity;
+use crate::interpreter::{Value, power};
+use std::collections::HashMap;
+
+pub fn register(map: &mut HashMap<String, BuiltinFn>) {
+    map.insert("min".into(), min);
+    map.insert("max".into(), max);
+    map.insert("clamp".into(), clamp);
+    map.insert("floor".into(), floor);
+    map.insert("ceil".into(), ceil);
+    map.insert("round".into(), round);
+    map.insert("sqrt".into(), sqrt);
+    map.insert("pow".into(), pow);
+    map.insert("sin".into(), sin);
+    map.insert("cos".into(), cos);
+    map.insert("atan2".into(), atan2);
+    map.insert("hypot".into(), hypot);
+    map.insert("sign".into(), sign);
+}
+
+fn number(name: &str, v: &Value) -> Result<f64, Value> {
+    match v {
+        Value::Num(_) | Value::Float(_) => Ok(v.as_f64().unwrap()),
+        _ => Err(Value::Error(format!("{} expects a number", name))),
+    }
+}
+
+// min(a, b, ...) or min(list); the smallest value is returned as it was given
+fn extreme(name: &str, args: Vec<Value>, pick_left: fn(f64, f64) -> bool) -> Value {
+    if let Err(e) = expect_arity(name, &args, 1) {
+        return e;
+    }
+
+    let items = match &args[..] {
+        [Value::List(items)] => items.lock().unwrap().clone(),
+        _ => args,
+    };
+    let mut best: Option<(f64, Value)> = None;
+    for v in items {
+        let n = match number(name, &v) {
+            Ok(n) => n,
+            Err(e) => return e,
+        };
+        if best.as_ref().is_none_or(|(b, _)| pick_left(n, *b)) {
+            best = Some((n, v));
+        }
+    }
+    match best {
+        Some((_, v)) => v,
+        None => Value::Error(format!("{} of an empty list", name)),
+    }
+}
+
+fn min(args: Vec<Value>) -> Value {
+    extreme("min", args, |a, b| a < b)
+}
+
+fn max(args: Vec<Value>) -> Value {
+    extreme("max", args, |a, b| a > b)
+}
+
+// clamp(value, low, high)
+fn clamp(args: Vec<Value>) -> Value {
+    if let Err(e) = expect_arity("clamp", &args, 3) {
+        return e;
+    }
+
+    let (v, lo, hi) = match (
+        number("clamp", &args[0]),
+        number("clamp", &args[1]),
+        number("clamp", &args[2]),
+    ) {
+        (Ok(v), Ok(lo), Ok(hi)) => (v, lo, hi),
+        (Err(e), _, _) | (_, Err(e), _) | (_, _, Err(e)) => return e,
+    };
+    if lo > hi {
+        return Value::Error(format!("clamp low {} is greater than high {}", lo, hi));
+    }
+
+    if v < lo {
+        args[1].clone()
+    } else if v > hi {
+        args[2].clone()
+    } else {
+        args[0].clone()
+    }
+}
+
+// floor, ceil and round give integers so the result can be used as a pixel position
+fn to_int(name: &str, args: Vec<Value>, f: fn(f64) -> f64) -> Value {
+    if let Err(e) = expect_arity(name, &args, 1) {
+        return e;
+    }
+
+    match &args[0] {
+        Value::Num(n) => Value::Num(*n),
+        v => match number(name, v) {
+            Ok(n) if (i64::MIN as f64..i64::MAX as f64).contains(&f(n)) => Value::Num(f(n) as i64),
+            Ok(n) => Value::Error(format!("{} of {} does not fit in an integer", name, n)),
+            Err(e) => e,
+        },
+    }
+}
+
+fn floor(args: Vec<Value>) -> Value {
+    to_int("floor", args, f64::floor)
+}
+
+fn ceil(args: Vec<Value>) -> Value {
+    to_int("ceil", args, f64::ceil)
+}
+
+fn round(args: Vec<Value>) -> Value {
+    to_int("round", args, f64::round)
+}
+
+fn sqrt(args: Vec<Value>) -> Value {
+    if let Err(e) = expect_arity("sqrt", &args, 1) {
+        return e;
+    }
+
+    match number("sqrt", &args[0]) {
+        Ok(n) if n < 0.0 => Value::Error(format!("sqrt of negative number {}", n)),
+        Ok(n) => Value::Float(n.sqrt()),
+        Err(e) => e,
+    }
+}
+
+fn pow(args: Vec<Value>) -> Value {
+    if let Err(e) = expect_arity("pow", &args, 2) {
+        return e;
+    }
+
+    if let Err(e) = number("pow", &args[0]).and(number("pow", &args[1])) {
+        return e;
+    }
+    match power(&args[0], &args[1]) {
+        Ok(v) => v,
+        Err(e) => Value::Error(e),
+    }
+}
+
+// angles are in radians
+fn sin(args: Vec<Value>) -> Value {
+    if let Err(e) = expect_arity("sin", &args, 1) {
+        return e;
+    }
+
+    match number("sin", &args[0]) {
+        Ok(n) => Value::Float(n.sin()),
+        Err(e) => e,
+    }
+}
+
+fn cos(args: Vec<Value>) -> Value {
+    if let Err(e) = expect_arity("cos", &args, 1) {
+        return e;
+    }
+
+    match number("cos", &args[0]) {
+        Ok(n) => Value::Float(n.cos()),
+        Err(e) => e,
+    }
+}
+
+// atan2(y, x), the angle of the point (x, y)
+fn atan2(args: Vec<Value>) -> Value {
+    if let Err(e) = expect_arity("atan2", &args, 2) {
+        return e;
+    }
+
+    match (number("atan2", &args[0]), number("atan2", &args[1])) {
+        (Ok(y), Ok(x)) => Value::Float(y.atan2(x)),
+        (Err(e), _) | (_, Err(e)) => e,
+    }
+}
+
+// hypot(x, y), the distance from (0, 0) to (x, y)
+fn hypot(args: Vec<Value>) -> Value {
+    if let Err(e) = expect_arity("hypot", &args, 2) {
+        return e;
+    }
+
+    match (number("hypot", &args[0]), number("hypot", &args[1])) {
+        (Ok(x), Ok(y)) => Value::Float(x.hypot(y)),
+        (Err(e), _) | (_, Err(e)) => e,
+    }
+}
+
+// -1, 0 or 1
+fn sign(args: Vec<Value>) -> Value {
+    if let Err(e) = expect_arity("sign", &args, 1) {
+        return e;
+    }
+
+    match number("sign", &args[0]) {
+        Ok(n) if n > 0.0 => Value::Num(1),
+        Ok(n) if n < 0.0 => Value::Num(-1),
+        Ok(_) => Value::Num(0),
+        Err(e) => e,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::format_value;
+
+    fn call(f: BuiltinFn, args: &[Value]) -> String {
+        format_value(&f(args.to_vec()))
+    }
+
+    #[test]
+    fn min_and_max_give_back_the_value_they_picked() {
+        let args = [Value::Num(3), Value::Float(1.5), Value::Num(2)];
+        assert_eq!(call(min, &args), "1.5");
+        assert_eq!(call(max, &args), "3");
+        assert_eq!(call(min, &[Value::list(vec![])]), "min of an empty list");
+    }
+
+    #[test]
+    fn clamp_keeps_a_value_inside_its_bounds() {
+        let clamped = |n| call(clamp, &[Value::Num(n), Value::Num(0), Value::Num(10)]);
+        assert_eq!([clamped(15), clamped(-1), clamped(4)], ["10", "0", "4"]);
+        assert_eq!(
+            call(clamp, &[Value::Num(1), Value::Num(5), Value::Num(0)]),
+            "clamp low 5 is greater than high 0"
+        );
+    }
+
+    #[test]
+    fn rounding_gives_integers() {
+        assert_eq!(call(floor, &[Value::Float(2.7)]), "2");
+        assert_eq!(call(ceil, &[Value::Float(2.1)]), "3");
+        assert_eq!(call(round, &[Value::Float(-2.5)]), "-3");
+        assert_eq!(
+            call(round, &[Value::Float(1e19)]),
+            "round of 10000000000000000000 does not fit in an integer"
+        );
+    }
+
+    #[test]
+    fn geometry() {
+        assert_eq!(call(sqrt, &[Value::Num(16)]), "4.0");
+        assert_eq!(call(sqrt, &[Value::Num(-1)]), "sqrt of negative number -1");
+        assert_eq!(call(hypot, &[Value::Num(3), Value::Num(4)]), "5.0");
+        assert_eq!(call(atan2, &[Value::Num(0), Value::Num(1)]), "0.0");
+        assert_eq!(call(cos, &[Value::Num(0)]), "1.0");
+        assert_eq!(call(sign, &[Value::Float(-0.5)]), "-1");
+        assert_eq!(call(pow, &[Value::Num(2), Value::Num(3)]), "8");
+        assert_eq!(call(sin, &[Value::Str("a".into())]), "sin expects a number");
+    }
+}
@@ -1,5 +1,6 @@
 use crate::interpreter::Value;
 use std::collections::HashMap;
+use std::time::Duration;
 
 pub fn expect_arity(name: &str, args: &[Value], expected: usize) -> Result<(), Value> {
     if args.len() < expected {
@@ -14,24 +15,43 @@ pub fn expect_arity(name: &str, args: &[Value], expected: usize) -> Result<(), V
     }
 }
 
+// A number of milliseconds as a duration, for sleep and timeouts. Anything
+// below zero waits no time at all.
+pub fn millis(name: &str, ms: f64) -> Result<Duration, String> {
+    if !ms.is_finite() {
+        return Err(format!("{} expects a finite number of milliseconds", name));
+    }
+    Duration::try_from_secs_f64(ms.max(0.0) / 1000.0)
+        .map_err(|_| format!("{} time is too long", name))
+}
+
 pub mod abs;
 pub mod background;
 pub mod beep;
+pub mod channels;
 pub mod click;
 pub mod color;
+pub mod counters;
 pub mod exit;
 pub mod get_color;
 pub mod get_mouse;
 pub mod get_resolution;
+pub mod list;
+pub mod map;
+pub mod math;
 pub mod mouse;
 pub mod press;
 pub mod print;
 pub mod process;
 pub mod random;
+pub mod range;
 pub mod release;
 pub mod scroll;
 pub mod sleep;
 pub mod string;
+pub mod strings;
+pub mod tasks;
+pub mod throw;
 pub mod time;
 
 pub type BuiltinFn = fn(Vec<Value>) -> Value;
@@ -56,4 +76,13 @@ pub fn register_all(map: &mut HashMap<String, BuiltinFn>) {
     get_color::register(map);
     process::register(map);
     abs::register(map);
+    list::register(map);
+    map::register(map);
+    range::register(map);
+    throw::register(map);
+    strings::register(map);
+    math::register(map);
+    tasks::register(map);
+    channels::register(map);
+    counters::register(map);
 }
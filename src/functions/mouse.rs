@@ -9,20 +9,25 @@ pub fn register(map: &mut HashMap<String, BuiltinFn>) {
     map.insert("mouse".into(), mouse);
 }
 
+// mouse(x, y, mode) or mouse(point, mode)
 fn mouse(args: Vec<Value>) -> Value {
+    let args = match args.first().map(|p| (p.field("x"), p.field("y"))) {
+        Some((Some(x), Some(y))) => [vec![x, y], args[1..].to_vec()].concat(),
+        _ => args,
+    };
     if let Err(e) = expect_arity("mouse", &args, 3) {
         return e;
     }
 
     let mut enigo = Enigo::new(&Settings::default()).unwrap();
 
-    let x = match args.get(0) {
-        Some(Value::Num(n)) => *n as i32,
+    let x = match args.get(0).and_then(Value::as_i64) {
+        Some(n) => n as i32,
         _ => return Value::Error("mouse expects number x".into()),
     };
 
-    let y = match args.get(1) {
-        Some(Value::Num(n)) => *n as i32,
+    let y = match args.get(1).and_then(Value::as_i64) {
+        Some(n) => n as i32,
         _ => return Value::Error("mouse expects number y".into()),
     };
 
@@ -1,7 +1,7 @@
 use enigo::{Button, Direction::Press, Enigo, Key, Keyboard, Mouse, Settings};
 use std::collections::HashMap;
 
-use super::BuiltinFn;
+use super::{BuiltinFn, release};
 use crate::functions::expect_arity;
 use crate::interpreter::Value;
 
@@ -147,5 +147,6 @@ fn press(args: Vec<Value>) -> Value {
         _ => return Value::Error("invalid press target".into()),
     }
 
+    release::hold(sym);
     Value::Bool(false)
 }
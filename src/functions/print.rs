@@ -1,5 +1,5 @@
 use super::BuiltinFn;
-use crate::interpreter::Value;
+use crate::interpreter::{Value, format_value};
 use std::collections::HashMap;
 
 pub fn register(map: &mut HashMap<String, BuiltinFn>) {
@@ -9,11 +9,8 @@ pub fn register(map: &mut HashMap<String, BuiltinFn>) {
 fn print(args: Vec<Value>) -> Value {
     for v in args {
         match v {
-            Value::Num(n) => print!("{}", n),
-            Value::Str(s) => print!("{}", s),
-            Value::Bool(b) => print!("{}", b),
-            Value::Symbol(s) => print!("{}", s),
             Value::Error(e) => eprint!("Error: {}", e),
+            v => print!("{}", format_value(&v)),
         }
     }
     println!();
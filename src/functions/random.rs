@@ -1,6 +1,7 @@
 use crate::functions::expect_arity;
 use crate::interpreter::Value;
 use rand::Rng;
+use rand::distr::Uniform;
 
 pub fn register(map: &mut std::collections::HashMap<String, fn(Vec<Value>) -> Value>) {
     map.insert("random".into(), random);
@@ -11,16 +12,68 @@ fn random(args: Vec<Value>) -> Value {
         return e;
     }
 
-    let (a, b) = match (&args[0], &args[1]) {
-        (Value::Num(x), Value::Num(y)) => (*x, *y),
+    let mut rng = rand::rng();
+
+    if let (Value::Num(a), Value::Num(b)) = (&args[0], &args[1]) {
+        if a > b {
+            return Value::Error("random range is invalid".into());
+        }
+        return Value::Num(rng.random_range(*a..=*b));
+    }
+
+    let (a, b) = match (args[0].as_f64(), args[1].as_f64()) {
+        (Some(x), Some(y)) => (x, y),
         _ => return Value::Error("random expects numbers".into()),
     };
 
+    if !a.is_finite() || !b.is_finite() {
+        return Value::Error("random expects finite numbers".into());
+    }
+
     if a > b {
         return Value::Error("random range is invalid".into());
     }
 
-    let mut rng = rand::rng();
-    let n = rng.random_range(a..=b);
-    Value::Num(n)
+    // the width itself can be too large for a float, as in random(-1e308, 1e308)
+    match Uniform::new_inclusive(a, b) {
+        Ok(range) => Value::Float(rng.sample(range)),
+        Err(_) => Value::Error("random range is too wide".into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn random_between(a: f64, b: f64) -> Value {
+        random(vec![Value::Float(a), Value::Float(b)])
+    }
+
+    #[test]
+    fn rejects_bounds_that_are_not_finite() {
+        assert!(matches!(
+            random_between(0.0, 10f64.powi(400)),
+            Value::Error(_)
+        ));
+        assert!(matches!(
+            random_between(f64::NEG_INFINITY, 0.0),
+            Value::Error(_)
+        ));
+        assert!(matches!(random_between(f64::NAN, 1.0), Value::Error(_)));
+    }
+
+    #[test]
+    fn rejects_ranges_too_wide_for_a_float() {
+        assert!(matches!(random_between(-1e308, 1e308), Value::Error(_)));
+    }
+
+    #[test]
+    fn stays_within_the_bounds() {
+        for _ in 0..100 {
+            match random_between(1.5, 2.5) {
+                Value::Float(x) => assert!((1.5..=2.5).contains(&x)),
+                v => panic!("expected a float, got {:?}", v),
+            }
+        }
+    }
 }
@@ -0,0 +1,73 @@
+use super::BuiltinFn;
+use crate::functions::expect_arity;
+use crate::interpreter::Value;
+use std::collections::HashMap;
+
+pub fn register(map: &mut HashMap<String, BuiltinFn>) {
+    map.insert("range".into(), range);
+}
+
+// range(end), range(start, end) or range(start, end, step); end is exclusive
+fn range(args: Vec<Value>) -> Value {
+    if let Err(e) = expect_arity("range", &args, 1) {
+        return e;
+    }
+
+    match bounds(&args) {
+        Ok((start, end, step)) => Value::list(steps(start, end, step).map(Value::Num).collect()),
+        Err(e) => Value::Error(e),
+    }
+}
+
+// the start, end and step that range() was called with
+pub fn bounds(args: &[Value]) -> Result<(i64, i64, i64), String> {
+    let mut nums = Vec::new();
+    for a in args.iter() {
+        match a {
+            Value::Num(n) => nums.push(*n),
+            _ => return Err("range expects integers".into()),
+        }
+    }
+
+    let bounds = match nums.as_slice() {
+        [end] => (0, *end, 1),
+        [start, end] => (*start, *end, 1),
+        [start, end, step, ..] => (*start, *end, *step),
+        [] => return Err("range expects 1 argument(s), got 0".into()),
+    };
+
+    if bounds.2 == 0 {
+        return Err("range step cannot be 0".into());
+    }
+    Ok(bounds)
+}
+
+// The numbers in the range one at a time, so `for i in range(...)` never
+// builds the list. Stops short of overflowing past the largest integer.
+pub fn steps(start: i64, end: i64, step: i64) -> impl Iterator<Item = i64> {
+    std::iter::successors(Some(start), move |i| i.checked_add(step))
+        .take_while(move |i| (step > 0 && *i < end) || (step < 0 && *i > end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stops_instead_of_overflowing() {
+        let near_max: Vec<i64> = steps(i64::MAX - 7, i64::MAX, 5).collect();
+        assert_eq!(near_max, [i64::MAX - 7, i64::MAX - 2]);
+        let near_min: Vec<i64> = steps(i64::MIN + 7, i64::MIN, -5).collect();
+        assert_eq!(near_min, [i64::MIN + 7, i64::MIN + 2]);
+    }
+
+    #[test]
+    fn counts_down_with_a_negative_step() {
+        assert_eq!(steps(5, 0, -2).collect::<Vec<_>>(), [5, 3, 1]);
+    }
+
+    #[test]
+    fn rejects_a_zero_step() {
+        assert!(bounds(&[Value::Num(0), Value::Num(5), Value::Num(0)]).is_err());
+    }
+}
@@ -1,14 +1,33 @@
 use enigo::{Button, Direction::Release, Enigo, Key, Keyboard, Mouse, Settings};
 use std::collections::HashMap;
+use std::sync::Mutex;
 
 use super::BuiltinFn;
 use crate::functions::expect_arity;
 use crate::interpreter::Value;
 
+// what `press` put down and nothing released yet, by the name it was given
+static HELD: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
 pub fn register(map: &mut HashMap<String, BuiltinFn>) {
     map.insert("release".into(), release);
 }
 
+pub fn hold(sym: &str) {
+    let mut held = HELD.lock().unwrap();
+    if !held.iter().any(|h| h == sym) {
+        held.push(sym.to_string());
+    }
+}
+
+// lets go of everything still held, when the script ends however it ends
+pub fn release_all() {
+    let held = std::mem::take(&mut *HELD.lock().unwrap());
+    for sym in held {
+        release(vec![Value::Str(sym)]);
+    }
+}
+
 fn release(args: Vec<Value>) -> Value {
     if let Err(e) = expect_arity("release", &args, 1) {
         return e;
@@ -147,5 +166,6 @@ fn release(args: Vec<Value>) -> Value {
         _ => return Value::Error("invalid release target".into()),
     }
 
+    HELD.lock().unwrap().retain(|h| h != sym);
     Value::Bool(false)
 }
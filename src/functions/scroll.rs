@@ -16,8 +16,8 @@ fn scroll(args: Vec<Value>) -> Value {
 
     let mut enigo = Enigo::new(&Settings::default()).unwrap();
 
-    let amount = match args.get(0) {
-        Some(Value::Num(n)) => *n as i32,
+    let amount = match args.get(0).and_then(Value::as_i64) {
+        Some(n) => n as i32,
         _ => return Value::Error("scroll expects number amount".into()),
     };
 
@@ -1,7 +1,8 @@
 use super::BuiltinFn;
-use crate::functions::expect_arity;
+use crate::functions::{expect_arity, millis};
 use crate::interpreter::Value;
-use std::{collections::HashMap, thread, time::Duration};
+use crate::task;
+use std::collections::HashMap;
 
 pub fn register(map: &mut HashMap<String, BuiltinFn>) {
     map.insert("sleep".into(), sleep);
@@ -12,14 +13,37 @@ fn sleep(args: Vec<Value>) -> Value {
         return e;
     }
 
-    let ms = match args.get(0) {
-        Some(Value::Num(n)) => *n,
+    let ms = match args.get(0).and_then(Value::as_f64) {
+        Some(n) => n,
         _ => return Value::Bool(false),
     };
 
-    if ms > 0 {
-        thread::sleep(Duration::from_millis(ms as u64));
+    match millis("sleep", ms) {
+        // returns early when the task calling it is cancelled
+        Ok(d) => task::sleep(d),
+        Err(e) => return Value::Error(e),
     }
 
     Value::Bool(false)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_times_that_are_not_finite_or_too_long() {
+        for ms in [1e23, 10f64.powi(400), f64::INFINITY, f64::NAN] {
+            assert!(matches!(sleep(vec![Value::Float(ms)]), Value::Error(_)));
+        }
+    }
+
+    #[test]
+    fn negative_times_do_not_wait() {
+        assert!(matches!(sleep(vec![Value::Num(-5)]), Value::Bool(false)));
+        assert!(matches!(
+            sleep(vec![Value::Float(-0.5)]),
+            Value::Bool(false)
+        ));
+    }
+}
@@ -0,0 +1,305 @@
+use super::BuiltinFn;
+use crate::functions::expect_arity;
+use crate::interpreter::{Value, format_value, values_equal};
+use std::collections::HashMap;
+
+// `len` lives in list.rs and already counts the characters of a string.
+// Positions and lengths here are in characters too, never in bytes.
+pub fn register(map: &mut HashMap<String, BuiltinFn>) {
+    map.insert("substr".into(), substr);
+    map.insert("find".into(), find);
+    map.insert("replace".into(), replace);
+    map.insert("split".into(), split);
+    map.insert("upper".into(), upper);
+    map.insert("lower".into(), lower);
+    map.insert("trim".into(), trim);
+    map.insert("starts_with".into(), starts_with);
+    map.insert("ends_with".into(), ends_with);
+    map.insert("contains".into(), contains);
+    map.insert("to_num".into(), to_num);
+    map.insert("to_str".into(), to_str);
+    map.insert("join".into(), join);
+}
+
+// bare words like RAlt are accepted wherever text is expected
+fn text<'a>(name: &str, v: &'a Value) -> Result<&'a str, Value> {
+    match v {
+        Value::Str(s) | Value::Symbol(s) => Ok(s),
+        _ => Err(Value::Error(format!("{} expects a string", name))),
+    }
+}
+
+fn count(name: &str, what: &str, v: &Value) -> Result<usize, Value> {
+    match v {
+        Value::Num(n) if *n >= 0 => Ok(*n as usize),
+        Value::Num(n) => Err(Value::Error(format!(
+            "{} {} must not be negative, got {}",
+            name, what, n
+        ))),
+        _ => Err(Value::Error(format!(
+            "{} {} must be an integer",
+            name, what
+        ))),
+    }
+}
+
+// substr(text, start) or substr(text, start, length)
+fn substr(args: Vec<Value>) -> Value {
+    if let Err(e) = expect_arity("substr", &args, 2) {
+        return e;
+    }
+
+    let s = match text("substr", &args[0]) {
+        Ok(s) => s,
+        Err(e) => return e,
+    };
+    let start = match count("substr", "start", &args[1]) {
+        Ok(n) => n,
+        Err(e) => return e,
+    };
+    let len = s.chars().count();
+    if start > len {
+        return Value::Error(format!(
+            "substr start {} is past the end of a string of length {}",
+            start, len
+        ));
+    }
+    // a length running past the end just takes the rest
+    let take = match args.get(2) {
+        Some(v) => match count("substr", "length", v) {
+            Ok(n) => n,
+            Err(e) => return e,
+        },
+        None => len,
+    };
+
+    Value::Str(s.chars().skip(start).take(take).collect())
+}
+
+// find(text, part) or find(text, part, from), -1 when not found
+fn find(args: Vec<Value>) -> Value {
+    if let Err(e) = expect_arity("find", &args, 2) {
+        return e;
+    }
+
+    let (s, part) = match (text("find", &args[0]), text("find", &args[1])) {
+        (Ok(s), Ok(p)) => (s, p),
+        (Err(e), _) | (_, Err(e)) => return e,
+    };
+    let from = match args.get(2) {
+        Some(v) => match count("find", "start", v) {
+            Ok(n) => n,
+            Err(e) => return e,
+        },
+        None => 0,
+    };
+
+    let offset = match s.char_indices().nth(from) {
+        Some((i, _)) => i,
+        None if from == s.chars().count() => s.len(),
+        None => return Value::Num(-1),
+    };
+    match s[offset..].find(part) {
+        Some(i) => Value::Num((from + s[offset..offset + i].chars().count()) as i64),
+        None => Value::Num(-1),
+    }
+}
+
+fn replace(args: Vec<Value>) -> Value {
+    if let Err(e) = expect_arity("replace", &args, 3) {
+        return e;
+    }
+
+    let parts = (
+        text("replace", &args[0]),
+        text("replace", &args[1]),
+        text("replace", &args[2]),
+    );
+    match parts {
+        (Ok(_), Ok(""), Ok(_)) => Value::Error("replace cannot search for an empty string".into()),
+        (Ok(s), Ok(from), Ok(to)) => Value::Str(s.replace(from, to)),
+        (Err(e), _, _) | (_, Err(e), _) | (_, _, Err(e)) => e,
+    }
+}
+
+// split(text) splits on whitespace, split(text, "") into characters
+fn split(args: Vec<Value>) -> Value {
+    if let Err(e) = expect_arity("split", &args, 1) {
+        return e;
+    }
+
+    let s = match text("split", &args[0]) {
+        Ok(s) => s,
+        Err(e) => return e,
+    };
+    let parts: Vec<Value> = match args.get(1) {
+        None => s.split_whitespace().map(|p| Value::Str(p.into())).collect(),
+        Some(sep) => match text("split", sep) {
+            Ok("") => s.chars().map(|c| Value::Str(c.to_string())).collect(),
+            Ok(sep) => s.split(sep).map(|p| Value::Str(p.into())).collect(),
+            Err(e) => return e,
+        },
+    };
+    Value::list(parts)
+}
+
+// join(list) or join(list, separator), items are printed like print would
+fn join(args: Vec<Value>) -> Value {
+    if let Err(e) = expect_arity("join", &args, 1) {
+        return e;
+    }
+
+    let items = match &args[0] {
+        Value::List(items) => items.lock().unwrap().clone(),
+        // join used to wait for tasks too
+        Value::Task(_) => {
+            return Value::Error("join expects a list, wait(task) waits for a task".into());
+        }
+        _ => return Value::Error("join expects a list".into()),
+    };
+    let sep = match args.get(1) {
+        Some(v) => match text("join", v) {
+            Ok(s) => s,
+            Err(e) => return e,
+        },
+        None => "",
+    };
+
+    let parts: Vec<String> = items.iter().map(format_value).collect();
+    Value::Str(parts.join(sep))
+}
+
+fn upper(args: Vec<Value>) -> Value {
+    if let Err(e) = expect_arity("upper", &args, 1) {
+        return e;
+    }
+
+    match text("upper", &args[0]) {
+        Ok(s) => Value::Str(s.to_uppercase()),
+        Err(e) => e,
+    }
+}
+
+fn lower(args: Vec<Value>) -> Value {
+    if let Err(e) = expect_arity("lower", &args, 1) {
+        return e;
+    }
+
+    match text("lower", &args[0]) {
+        Ok(s) => Value::Str(s.to_lowercase()),
+        Err(e) => e,
+    }
+}
+
+fn trim(args: Vec<Value>) -> Value {
+    if let Err(e) = expect_arity("trim", &args, 1) {
+        return e;
+    }
+
+    match text("trim", &args[0]) {
+        Ok(s) => Value::Str(s.trim().into()),
+        Err(e) => e,
+    }
+}
+
+fn starts_with(args: Vec<Value>) -> Value {
+    if let Err(e) = expect_arity("starts_with", &args, 2) {
+        return e;
+    }
+
+    match (text("starts_with", &args[0]), text("starts_with", &args[1])) {
+        (Ok(s), Ok(p)) => Value::Bool(s.starts_with(p)),
+        (Err(e), _) | (_, Err(e)) => e,
+    }
+}
+
+fn ends_with(args: Vec<Value>) -> Value {
+    if let Err(e) = expect_arity("ends_with", &args, 2) {
+        return e;
+    }
+
+    match (text("ends_with", &args[0]), text("ends_with", &args[1])) {
+        (Ok(s), Ok(p)) => Value::Bool(s.ends_with(p)),
+        (Err(e), _) | (_, Err(e)) => e,
+    }
+}
+
+// contains(text, part) or contains(list, value)
+fn contains(args: Vec<Value>) -> Value {
+    if let Err(e) = expect_arity("contains", &args, 2) {
+        return e;
+    }
+
+    if let Value::List(items) = &args[0] {
+        let items = items.lock().unwrap().clone();
+        return Value::Bool(items.iter().any(|v| values_equal(v, &args[1])));
+    }
+    match (text("contains", &args[0]), text("contains", &args[1])) {
+        (Ok(s), Ok(p)) => Value::Bool(s.contains(p)),
+        (Err(_), _) => Value::Error("contains expects a string or list".into()),
+        (_, Err(e)) => e,
+    }
+}
+
+// "42" becomes an integer, "4.2" a float, surrounding whitespace is ignored
+fn to_num(args: Vec<Value>) -> Value {
+    if let Err(e) = expect_arity("to_num", &args, 1) {
+        return e;
+    }
+
+    let s = match &args[0] {
+        Value::Num(_) | Value::Float(_) => return args[0].clone(),
+        Value::Bool(b) => return Value::Num(*b as i64),
+        v => match text("to_num", v) {
+            Ok(s) => s.trim(),
+            Err(_) => return Value::Error("to_num expects a string or number".into()),
+        },
+    };
+
+    if let Ok(n) = s.parse::<i64>() {
+        return Value::Num(n);
+    }
+    match s.parse::<f64>() {
+        Ok(n) if n.is_finite() => Value::Float(n),
+        _ => Value::Error(format!("cannot convert \"{}\" to a number", s)),
+    }
+}
+
+fn to_str(args: Vec<Value>) -> Value {
+    if let Err(e) = expect_arity("to_str", &args, 1) {
+        return e;
+    }
+
+    Value::Str(format_value(&args[0]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task::Task;
+
+    fn joined(args: Vec<Value>) -> String {
+        match join(args) {
+            Value::Str(s) => s,
+            v => panic!("expected a string, got {:?}", v),
+        }
+    }
+
+    #[test]
+    fn join_with_and_without_a_separator() {
+        let list = Value::list(vec![
+            Value::Str("a".into()),
+            Value::Num(1),
+            Value::Float(2.5),
+        ]);
+        assert_eq!(joined(vec![list.clone()]), "a12.5");
+        assert_eq!(joined(vec![list, Value::Str(", ".into())]), "a, 1, 2.5");
+    }
+
+    #[test]
+    fn join_points_tasks_to_wait() {
+        let v = join(vec![Value::Task(Task::new())]);
+        assert!(matches!(v, Value::Error(e) if e.contains("wait(task)")));
+        assert!(matches!(join(vec![Value::Num(5)]), Value::Error(e) if e == "join expects a list"));
+    }
+}
@@ -0,0 +1,103 @@
+use super::BuiltinFn;
+use crate::functions::expect_arity;
+use crate::interpreter::Value;
+use crate::task::{State, Task};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+// `let t = async { ... }` gives the task these work on
+pub fn register(map: &mut HashMap<String, BuiltinFn>) {
+    map.insert("wait".into(), wait);
+    map.insert("cancel".into(), cancel);
+    map.insert("is_running".into(), is_running);
+}
+
+fn task<'a>(name: &str, args: &'a [Value]) -> Result<&'a Arc<Task>, Value> {
+    match &args[0] {
+        Value::Task(t) => Ok(t),
+        _ => Err(Value::Error(format!("{} expects a task", name))),
+    }
+}
+
+// wait(task) waits for the task to end and gives back what its block
+// returned; a cancelled task gives false, a failed one throws its error again,
+// still pointing at the line in the task where it happened.
+fn wait(args: Vec<Value>) -> Value {
+    if let Err(e) = expect_arity("wait", &args, 1) {
+        return e;
+    }
+
+    let t = match task("wait", &args) {
+        Ok(t) => t,
+        Err(e) => return e,
+    };
+    match t.join() {
+        Some(State::Done(v)) => v,
+        Some(State::Failed(e)) => Value::Thrown(Box::new(e)),
+        // the joined task was cancelled, or the one waiting was
+        Some(State::Cancelled) | Some(State::Running) | None => Value::Bool(false),
+    }
+}
+
+// asks the task to stop, it ends before its next statement
+fn cancel(args: Vec<Value>) -> Value {
+    if let Err(e) = expect_arity("cancel", &args, 1) {
+        return e;
+    }
+
+    match task("cancel", &args) {
+        Ok(t) => {
+            t.cancel();
+            Value::Bool(false)
+        }
+        Err(e) => e,
+    }
+}
+
+fn is_running(args: Vec<Value>) -> Value {
+    if let Err(e) = expect_arity("is_running", &args, 1) {
+        return e;
+    }
+
+    match task("is_running", &args) {
+        Ok(t) => Value::Bool(matches!(t.state(), State::Running)),
+        Err(e) => e,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Span;
+    use crate::interpreter::Error;
+
+    #[test]
+    fn wait_gives_what_the_task_returned() {
+        let task = Task::new();
+        let t = task.clone();
+        task.start(move || t.finish(State::Done(Value::Num(5))));
+        assert!(matches!(wait(vec![Value::Task(task)]), Value::Num(5)));
+    }
+
+    #[test]
+    fn wait_throws_the_error_where_it_happened_in_the_task() {
+        let mut e = Error::from("division by zero");
+        e.span = Some(Span { line: 3, col: 13 });
+        let task = Task::new();
+        let t = task.clone();
+        task.start(move || t.finish(State::Failed(e)));
+        match wait(vec![Value::Task(task)]) {
+            Value::Thrown(e) => {
+                assert_eq!(e.message(), "division by zero");
+                assert_eq!(e.span.map(|s| (s.line, s.col)), Some((3, 13)));
+            }
+            v => panic!("expected the task's error, got {:?}", v),
+        }
+    }
+
+    #[test]
+    fn wait_only_takes_tasks() {
+        let list = Value::list(Vec::new());
+        assert!(matches!(wait(vec![list]), Value::Error(e) if e == "wait expects a task"));
+    }
+}
@@ -0,0 +1,16 @@
+use super::BuiltinFn;
+use crate::functions::expect_arity;
+use crate::interpreter::{Error, Value};
+use std::collections::HashMap;
+
+pub fn register(map: &mut HashMap<String, BuiltinFn>) {
+    map.insert("throw".into(), throw);
+}
+
+fn throw(args: Vec<Value>) -> Value {
+    if let Err(e) = expect_arity("throw", &args, 1) {
+        return e;
+    }
+
+    Value::Thrown(Box::new(Error::thrown(args[0].clone())))
+}
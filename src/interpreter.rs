@@ -1,26 +1,282 @@
-use device_query::{DeviceQuery, DeviceState, Keycode};
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
-use std::thread::JoinHandle;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Instant;
 
-use crate::ast::{Expr, Node, Op};
+use crate::ast::{AssignTarget, Expr, ExprKind, Node, NodeKind, Op, Pattern, SelectArm, Span};
+use crate::channel::{Channel, Recv};
+use crate::diagnostic;
+use crate::functions::{millis, range};
+use crate::input;
+use crate::lock::Locks;
+use crate::module::Module;
+use crate::scope::Scope;
+use crate::task::{self, State, Task};
 
 #[derive(Clone, Debug)]
 pub enum Value {
     Num(i64),
+    Float(f64),
     Str(String),
     Bool(bool),
     Symbol(String),
+    List(Arc<Mutex<Vec<Value>>>),
+    Map(Arc<Mutex<BTreeMap<String, Value>>>),
+    Func(Arc<Function>),
+    Module(Arc<Namespace>),
+    Struct(Arc<Instance>),
+    Type(Arc<StructType>),
+    Task(Arc<Task>),
+    // the two ends of a channel()
+    Sender(Arc<Channel>),
+    Receiver(Arc<Channel>),
+    // counter(), changed in place by incr and decr
+    Counter(Arc<AtomicI64>),
     Error(String),
+    // what throw(v), or wait on a failed task, gives back, so the error
+    // reaches `catch` as it was thrown
+    Thrown(Box<Error>),
+}
+
+// A user function together with the scope it was defined in.
+pub struct Function {
+    pub name: String,
+    pub params: Vec<(String, Option<Expr>)>,
+    pub body: Vec<Node>,
+    pub env: Arc<Scope>,
+    // the file it was written in
+    pub module: usize,
+}
+
+// What `import "..." as h` binds to `h`: the top-level variables of the
+// imported file. `h.x` reads and writes them directly.
+#[derive(Debug)]
+pub struct Namespace {
+    pub file: String,
+    pub scope: Arc<Scope>,
+}
+
+// What `struct Point { x, y }` binds to `Point`; calling it builds an instance.
+#[derive(Debug)]
+pub struct StructType {
+    pub name: String,
+    pub fields: Vec<String>,
+}
+
+// Field values in declaration order. Like lists, instances are shared, so a
+// field assigned through one variable changes it for every other.
+#[derive(Debug)]
+pub struct Instance {
+    pub ty: Arc<StructType>,
+    values: Mutex<Vec<Value>>,
+}
+
+impl Instance {
+    fn get(&self, field: &str) -> Option<Value> {
+        let i = self.ty.fields.iter().position(|f| f == field)?;
+        Some(self.values.lock().unwrap()[i].clone())
+    }
+
+    fn set(&self, field: &str, v: Value) -> bool {
+        match self.ty.fields.iter().position(|f| f == field) {
+            Some(i) => {
+                self.values.lock().unwrap()[i] = v;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+// Point is declared in every file, builtins like get_mouse return one
+fn point_type() -> Arc<StructType> {
+    static POINT: OnceLock<Arc<StructType>> = OnceLock::new();
+    POINT
+        .get_or_init(|| {
+            Arc::new(StructType {
+                name: "Point".into(),
+                fields: vec!["x".into(), "y".into()],
+            })
+        })
+        .clone()
+}
+
+// the top-level scope of a file
+fn globals() -> Arc<Scope> {
+    let scope = Scope::new(None);
+    scope.declare("Point", Value::Type(point_type()));
+    scope
+}
+
+// the captured scope may contain the function itself
+impl std::fmt::Debug for Function {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "<fn {}>", self.name)
+    }
+}
+
+impl Value {
+    pub fn list(items: Vec<Value>) -> Value {
+        Value::List(Arc::new(Mutex::new(items)))
+    }
+
+    pub fn map(entries: BTreeMap<String, Value>) -> Value {
+        Value::Map(Arc::new(Mutex::new(entries)))
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Num(n) => Some(*n as f64),
+            Value::Float(f) => Some(*f),
+            _ => None,
+        }
+    }
+
+    pub fn point(x: i64, y: i64) -> Value {
+        Value::Struct(Arc::new(Instance {
+            ty: point_type(),
+            values: Mutex::new(vec![Value::Num(x), Value::Num(y)]),
+        }))
+    }
+
+    // a field of a struct, for builtins that accept one
+    pub fn field(&self, name: &str) -> Option<Value> {
+        match self {
+            Value::Struct(s) => s.get(name),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Value::Num(n) => Some(*n),
+            Value::Float(f) => Some(f.round() as i64),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Clone)]
 pub struct Context {
-    vars: Arc<Mutex<HashMap<String, Value>>>,
-    funcs: Arc<Mutex<HashMap<String, Node>>>,
+    scope: Arc<Scope>,
+    frames: Option<Arc<Frame>>,
     cmds: Arc<HashMap<String, fn(Vec<Value>) -> Value>>,
-    tasks: Arc<Mutex<Vec<JoinHandle<()>>>>,
-    error: Arc<Mutex<Option<String>>>,
+    modules: Arc<Vec<Module>>,
+    // the file the running code comes from
+    module: usize,
+    // each imported file runs once, later imports get the same namespace
+    namespaces: Arc<Mutex<HashMap<usize, Value>>>,
+    tasks: Arc<Mutex<Vec<Arc<Task>>>>,
+    locks: Arc<Locks>,
+    error: Arc<Mutex<Option<Error>>>,
+}
+
+// A thrown value, where it was thrown from and the user functions that were
+// running at the time, innermost first. Locations carry the module they are in.
+#[derive(Clone, Debug)]
+pub struct Error {
+    pub value: Value,
+    pub span: Option<Span>,
+    pub module: usize,
+    pub stack: Vec<(String, Span, usize)>,
+    // set when a cancelled task unwinds, `catch` lets it through
+    pub cancelled: bool,
+}
+
+// One user function call, linked to the call it was made from.
+#[derive(Debug)]
+struct Frame {
+    name: String,
+    call: Span,
+    module: usize,
+    parent: Option<Arc<Frame>>,
+}
+
+impl Error {
+    // keeps the innermost location when the error unwinds through outer nodes
+    fn at(mut self, span: Span, ctx: &Context) -> Self {
+        if self.span.is_none() {
+            self.span = Some(span);
+            self.module = ctx.module;
+            let mut frame = ctx.frames.as_ref();
+            while let Some(f) = frame {
+                self.stack.push((f.name.clone(), f.call, f.module));
+                frame = f.parent.as_ref();
+            }
+        }
+        self
+    }
+
+    pub fn thrown(value: Value) -> Self {
+        Self {
+            value,
+            ..Error::from("")
+        }
+    }
+
+    fn cancelled() -> Self {
+        Self {
+            cancelled: true,
+            ..Error::from("task cancelled")
+        }
+    }
+
+    pub fn message(&self) -> String {
+        format_value(&self.value)
+    }
+
+    pub fn render(&self, modules: &[Module]) -> String {
+        let mut out = format!("Runtime error: {}\n", self.message());
+        if let Some(span) = self.span {
+            let m = &modules[self.module];
+            out.push_str(&diagnostic::snippet(&m.file, &m.src, span));
+        }
+        for (name, call, module) in self.stack.iter() {
+            out.push_str(&format!(
+                "  = in {}, called at {}:{}:{}\n",
+                name, modules[*module].file, call.line, call.col
+            ));
+        }
+        out
+    }
+
+    // what a `catch (e)` block sees
+    fn to_value(&self) -> Value {
+        let (line, col) = self.span.map_or((0, 0), |s| (s.line, s.col));
+        let mut entries = BTreeMap::new();
+        entries.insert("message".into(), Value::Str(self.message()));
+        entries.insert("value".into(), self.value.clone());
+        entries.insert("line".into(), Value::Num(line as i64));
+        entries.insert("col".into(), Value::Num(col as i64));
+        Value::map(entries)
+    }
+}
+
+impl From<String> for Error {
+    fn from(msg: String) -> Self {
+        Self {
+            value: Value::Str(msg),
+            span: None,
+            module: 0,
+            stack: Vec::new(),
+            cancelled: false,
+        }
+    }
+}
+
+impl From<&str> for Error {
+    fn from(msg: &str) -> Self {
+        msg.to_string().into()
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.span {
+            Some(s) => write!(f, "line {}, column {}: {}", s.line, s.col, self.message()),
+            None => write!(f, "{}", self.message()),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -32,17 +288,21 @@ enum Flow {
 }
 
 impl Context {
-    pub fn new(cmds: HashMap<String, fn(Vec<Value>) -> Value>) -> Self {
+    pub fn new(cmds: HashMap<String, fn(Vec<Value>) -> Value>, modules: Arc<Vec<Module>>) -> Self {
         Self {
-            vars: Arc::new(Mutex::new(HashMap::new())),
-            funcs: Arc::new(Mutex::new(HashMap::new())),
+            scope: globals(),
+            frames: None,
             cmds: Arc::new(cmds),
+            modules,
+            module: 0,
+            namespaces: Arc::new(Mutex::new(HashMap::new())),
             tasks: Arc::new(Mutex::new(Vec::new())),
+            locks: Arc::new(Locks::default()),
             error: Arc::new(Mutex::new(None)),
         }
     }
 
-    pub fn run(&self, nodes: &[Node]) -> Result<(), String> {
+    pub fn run(&self, nodes: &[Node]) -> Result<(), Error> {
         for n in nodes.iter() {
             if let Some(err) = self.error.lock().unwrap().clone() {
                 return Err(err);
@@ -58,16 +318,16 @@ impl Context {
         Ok(())
     }
 
-    pub fn join_tasks(&self) -> Result<(), String> {
+    pub fn join_tasks(&self) -> Result<(), Error> {
         loop {
-            let handle = {
+            let task = {
                 let mut tasks = self.tasks.lock().unwrap();
                 tasks.pop()
             };
 
-            match handle {
-                Some(h) => {
-                    let _ = h.join();
+            match task {
+                Some(t) => {
+                    t.join();
                 }
                 None => break,
             }
@@ -80,56 +340,188 @@ impl Context {
         Ok(())
     }
 
-    fn exec(&self, n: &Node) -> Result<Flow, String> {
+    // Runs the functions given to on_exit, the last one first. They run
+    // however the script ended, so an error that stopped it is set aside
+    // meanwhile.
+    pub fn run_exit_handlers(&self, handlers: Vec<Value>) -> Result<(), Error> {
+        let pending = self.error.lock().unwrap().take();
+        let mut result = Ok(());
+        for handler in handlers.into_iter().rev() {
+            let Value::Func(f) = handler else {
+                continue;
+            };
+            let call = Span { line: 0, col: 0 };
+            if let Err(mut e) = task::shielded(|| self.call_function(&f, Vec::new(), call)) {
+                // the handler itself has no call site to show
+                e.stack.pop();
+                result = result.and(Err(e));
+            }
+        }
+        if pending.is_some() {
+            *self.error.lock().unwrap() = pending;
+        }
+        result
+    }
+
+    // a context sharing everything but with a fresh frame on top of the current one
+    fn child(&self) -> Context {
+        Context {
+            scope: Scope::new(Some(self.scope.clone())),
+            ..self.clone()
+        }
+    }
+
+    fn exec_block(&self, body: &[Node]) -> Result<Flow, Error> {
+        // also catches a cancelled task spinning in an empty loop
+        if task::cancelled() {
+            return Err(Error::cancelled());
+        }
+        // loop bodies come through here, so a busy loop shares its worker
+        task::yield_point();
+        self.child().exec_body(body)
+    }
+
+    fn exec_body(&self, body: &[Node]) -> Result<Flow, Error> {
+        for stmt in body.iter() {
+            match self.exec(stmt)? {
+                Flow::None => {}
+                f => return Ok(f),
+            }
+        }
+        Ok(Flow::None)
+    }
+
+    fn exec(&self, n: &Node) -> Result<Flow, Error> {
         if self.error.lock().unwrap().is_some() {
             return Ok(Flow::None);
         }
+        if task::cancelled() {
+            return Err(Error::cancelled());
+        }
+
+        self.exec_kind(&n.kind, n.span)
+            .map_err(|e| e.at(n.span, self))
+    }
 
+    fn exec_kind(&self, n: &NodeKind, span: Span) -> Result<Flow, Error> {
         match n {
-            Node::VarDecl { name, value } => {
+            NodeKind::VarDecl {
+                name,
+                value,
+                constant,
+            } => {
                 let v = self.eval(value)?;
-                self.vars.lock().unwrap().insert(name.clone(), v);
+                if *constant {
+                    self.scope.declare_const(name, v);
+                } else {
+                    self.scope.declare(name, v);
+                }
             }
 
-            Node::Assign { name, value } => {
-                let v = self.eval(value)?;
-                let mut vars = self.vars.lock().unwrap();
-
-                if !vars.contains_key(name) {
-                    return Err(format!("variable '{}' is not defined", name));
+            NodeKind::Assign { target, op, value } => match target {
+                AssignTarget::Var(name) => {
+                    let v = self.eval(value)?;
+                    match op {
+                        // read and written under one lock, so `n += 1` in two tasks adds 2
+                        Some(op) => self.scope.update(name, |old| binary(old, *op, v))?,
+                        None => self.scope.assign(name, v)?,
+                    }
                 }
+                AssignTarget::Field(target, field) => {
+                    let target = self.eval(target)?;
+                    let v = self.eval(value)?;
+                    match (op, &target) {
+                        (Some(op), Value::Struct(s)) => {
+                            let Some(i) = s.ty.fields.iter().position(|f| f == field) else {
+                                return Err(
+                                    format!("{} has no field '{}'", s.ty.name, field).into()
+                                );
+                            };
+                            update_slot(&s.values, |values| Ok(&mut values[i]), *op, v)?;
+                        }
+                        (Some(op), Value::Module(m)) => {
+                            m.scope.update(field, |old| binary(old, *op, v))?
+                        }
+                        (Some(_), _) => {
+                            return Err("only structs and imported files have fields".into());
+                        }
+                        (None, _) => set_field(&target, field, v)?,
+                    }
+                }
+                AssignTarget::Index(target, index) => {
+                    let target = self.eval(target)?;
+                    let index = self.eval(index)?;
+                    let v = self.eval(value)?;
 
-                vars.insert(name.clone(), v);
-            }
+                    match (target, op) {
+                        (Value::List(items), Some(op)) => {
+                            update_slot(
+                                &items,
+                                |items| {
+                                    let i = list_index(&index, items.len())?;
+                                    Ok(&mut items[i])
+                                },
+                                *op,
+                                v,
+                            )?;
+                        }
+                        (Value::List(items), None) => {
+                            let mut items = items.lock().unwrap();
+                            let i = list_index(&index, items.len())?;
+                            items[i] = v;
+                        }
+                        (Value::Map(entries), Some(op)) => {
+                            let key = map_key(&index)?;
+                            let missing = || format!("key \"{}\" not found in map", key).into();
+                            update_slot(
+                                &entries,
+                                |entries| entries.get_mut(&key).ok_or_else(missing),
+                                *op,
+                                v,
+                            )?;
+                        }
+                        (Value::Map(entries), None) => {
+                            let key = map_key(&index)?;
+                            entries.lock().unwrap().insert(key, v);
+                        }
+                        _ => return Err("only lists and maps can be indexed".into()),
+                    }
+                }
+            },
 
-            Node::Call { name, args } => {
+            NodeKind::Call { name, args } => {
                 let vals = args
                     .iter()
                     .map(|a| self.eval(a))
                     .collect::<Result<Vec<_>, _>>()?;
 
-                if let Some(cmd) = self.cmds.get(name) {
-                    let result = cmd(vals);
-                    if let Value::Error(e) = result {
-                        *self.error.lock().unwrap() = Some(e);
-                    }
-                } else {
-                    let result = self.eval(&Expr::Call {
-                        name: name.clone(),
-                        args: args.clone(),
-                    })?;
+                self.call_named(name, vals, span)?;
+            }
 
-                    if let Value::Error(e) = result {
-                        *self.error.lock().unwrap() = Some(e);
-                    }
-                }
+            NodeKind::Expr(e) => {
+                self.eval(e)?;
+            }
+
+            NodeKind::Function { name, params, body } => {
+                let f = Function {
+                    name: name.clone(),
+                    params: params.clone(),
+                    body: body.clone(),
+                    env: self.scope.clone(),
+                    module: self.module,
+                };
+                self.scope.declare(name, Value::Func(Arc::new(f)));
             }
 
-            Node::Function { name, .. } => {
-                self.funcs.lock().unwrap().insert(name.clone(), n.clone());
+            NodeKind::Struct { name, fields } => {
+                let t = StructType {
+                    name: name.clone(),
+                    fields: fields.clone(),
+                };
+                self.scope.declare(name, Value::Type(Arc::new(t)));
             }
 
-            Node::Return(expr) => {
+            NodeKind::Return(expr) => {
                 let v = match expr {
                     Some(e) => self.eval(e)?,
                     None => Value::Bool(false),
@@ -137,196 +529,129 @@ impl Context {
                 return Ok(Flow::Return(v));
             }
 
-            Node::Async { body } => {
-                let ctx = self.clone();
-                let body = body.clone();
+            NodeKind::Async { body } => {
+                self.spawn(body);
+            }
+
+            NodeKind::Await { key, negated, body } => {
+                let key = key_name(self.eval(key)?)?;
+
+                loop {
+                    let pressed = input::watch(task::waker())?.pressed(&key);
 
-                let handle = std::thread::spawn(move || {
-                    if let Err(e) = ctx.run(&body) {
-                        *ctx.error.lock().unwrap() = Some(e);
+                    let matched = if *negated { !pressed } else { pressed };
+
+                    if matched {
+                        return self.exec_block(body);
                     }
-                });
 
-                self.tasks.lock().unwrap().push(handle);
+                    task::wait(None);
+                    if task::cancelled() {
+                        return Err(Error::cancelled());
+                    }
+                }
             }
 
-            Node::Await { key, negated, body } => {
-                let device = DeviceState::new();
+            NodeKind::Select { arms } => return self.select(arms),
 
-                let key = match self.eval(key)? {
-                    Value::Symbol(s) => s,
-                    Value::Str(s) => s,
-                    Value::Num(n) => n.to_string(),
-                    _ => return Err("key must be a symbol, string, or a number".into()),
+            NodeKind::Lock { name, body } => {
+                let Some(_guard) = self.locks.acquire(name) else {
+                    return Err(Error::cancelled());
                 };
+                return self.exec_block(body);
+            }
 
-                loop {
-                    let keys = device.get_keys();
-                    let mouse = device.get_mouse().button_pressed;
-
-                    let pressed = match key.as_str() {
-                        "LMB" => mouse[1],
-                        "RMB" => mouse[2],
-                        "MMB" => mouse[3],
-                        "MB4" => mouse[4],
-                        "MB5" => mouse[5],
-
-                        "Enter" | "Return" => keys.contains(&Keycode::Enter),
-                        "Space" => keys.contains(&Keycode::Space),
-                        "Tab" => keys.contains(&Keycode::Tab),
-                        "Esc" | "Escape" => keys.contains(&Keycode::Escape),
-                        "Backspace" => keys.contains(&Keycode::Backspace),
-
-                        "Ctrl" | "Control" => {
-                            keys.contains(&Keycode::LControl) || keys.contains(&Keycode::RControl)
-                        }
-                        "LCtrl" | "LControl" => keys.contains(&Keycode::LControl),
-                        "RCtrl" | "RControl" => keys.contains(&Keycode::RControl),
-                        "Alt" => keys.contains(&Keycode::LAlt) || keys.contains(&Keycode::RAlt),
-                        "LAlt" => keys.contains(&Keycode::LAlt),
-                        "RAlt" | "AltGr" => keys.contains(&Keycode::RAlt),
-                        "Shift" => {
-                            keys.contains(&Keycode::LShift) || keys.contains(&Keycode::RShift)
-                        }
-                        "LShift" => keys.contains(&Keycode::LShift),
-                        "RShift" => keys.contains(&Keycode::RShift),
-                        "Super" | "Meta" => {
-                            keys.contains(&Keycode::LMeta) || keys.contains(&Keycode::RMeta)
-                        }
-                        "CapsLock" | "Caps" => keys.contains(&Keycode::CapsLock),
-
-                        "Insert" => keys.contains(&Keycode::Insert),
-                        "Delete" | "Del" => keys.contains(&Keycode::Delete),
-                        "Home" => keys.contains(&Keycode::Home),
-                        "End" => keys.contains(&Keycode::End),
-                        "PageUp" | "PgUp" => keys.contains(&Keycode::PageUp),
-                        "PageDown" | "PgDown" => keys.contains(&Keycode::PageDown),
-
-                        "Up" => keys.contains(&Keycode::Up),
-                        "Down" => keys.contains(&Keycode::Down),
-                        "Left" => keys.contains(&Keycode::Left),
-                        "Right" => keys.contains(&Keycode::Right),
-
-                        k if k.starts_with('F') => match k {
-                            "F1" => keys.contains(&Keycode::F1),
-                            "F2" => keys.contains(&Keycode::F2),
-                            "F3" => keys.contains(&Keycode::F3),
-                            "F4" => keys.contains(&Keycode::F4),
-                            "F5" => keys.contains(&Keycode::F5),
-                            "F6" => keys.contains(&Keycode::F6),
-                            "F7" => keys.contains(&Keycode::F7),
-                            "F8" => keys.contains(&Keycode::F8),
-                            "F9" => keys.contains(&Keycode::F9),
-                            "F10" => keys.contains(&Keycode::F10),
-                            "F11" => keys.contains(&Keycode::F11),
-                            "F12" => keys.contains(&Keycode::F12),
-                            _ => false,
-                        },
-
-                        k if k.len() == 1 && k.chars().next().unwrap().is_ascii_digit() => {
-                            let d = k.chars().next().unwrap();
-                            keys.iter().any(|kc| match kc {
-                                Keycode::Key0 => d == '0',
-                                Keycode::Key1 => d == '1',
-                                Keycode::Key2 => d == '2',
-                                Keycode::Key3 => d == '3',
-                                Keycode::Key4 => d == '4',
-                                Keycode::Key5 => d == '5',
-                                Keycode::Key6 => d == '6',
-                                Keycode::Key7 => d == '7',
-                                Keycode::Key8 => d == '8',
-                                Keycode::Key9 => d == '9',
-                                _ => false,
-                            })
-                        }
+            NodeKind::Try {
+                body,
+                catch,
+                finally,
+            } => {
+                let mut result = self.exec_block(body);
 
-                        k if k.len() == 1 => {
-                            let c = k.chars().next().unwrap().to_ascii_lowercase();
-                            keys.iter().any(|kc| match kc {
-                                Keycode::A => c == 'a',
-                                Keycode::B => c == 'b',
-                                Keycode::C => c == 'c',
-                                Keycode::D => c == 'd',
-                                Keycode::E => c == 'e',
-                                Keycode::F => c == 'f',
-                                Keycode::G => c == 'g',
-                                Keycode::H => c == 'h',
-                                Keycode::I => c == 'i',
-                                Keycode::J => c == 'j',
-                                Keycode::K => c == 'k',
-                                Keycode::L => c == 'l',
-                                Keycode::M => c == 'm',
-                                Keycode::N => c == 'n',
-                                Keycode::O => c == 'o',
-                                Keycode::P => c == 'p',
-                                Keycode::Q => c == 'q',
-                                Keycode::R => c == 'r',
-                                Keycode::S => c == 's',
-                                Keycode::T => c == 't',
-                                Keycode::U => c == 'u',
-                                Keycode::V => c == 'v',
-                                Keycode::W => c == 'w',
-                                Keycode::X => c == 'x',
-                                Keycode::Y => c == 'y',
-                                Keycode::Z => c == 'z',
-                                _ => false,
-                            })
-                        }
+                if let (Err(e), Some((name, handler))) = (&result, catch)
+                    && !e.cancelled
+                {
+                    let inner = self.child();
+                    if let Some(name) = name {
+                        inner.scope.declare(name, e.to_value());
+                    }
+                    result = inner.exec_body(handler);
+                }
 
-                        _ => false,
+                if let Some(finally) = finally {
+                    let cleanup = || self.exec_block(finally);
+                    let flow = match &result {
+                        // a cancelled task still gets to clean up
+                        Err(e) if e.cancelled => task::shielded(cleanup),
+                        _ => cleanup(),
                     };
-
-                    let matched = if *negated { !pressed } else { pressed };
-
-                    if matched {
-                        for stmt in body.iter() {
-                            match self.exec(stmt)? {
-                                Flow::None => {}
-                                f => return Ok(f),
-                            }
-                        }
-                        break;
+                    match flow? {
+                        Flow::None => {}
+                        f => return Ok(f),
                     }
-
-                    std::thread::sleep(std::time::Duration::from_millis(10));
                 }
+
+                return result;
             }
 
-            Node::Break => return Ok(Flow::Break),
-            Node::Continue => return Ok(Flow::Continue),
+            NodeKind::Break => return Ok(Flow::Break),
+            NodeKind::Continue => return Ok(Flow::Continue),
 
-            Node::Loop { times, body } => {
+            NodeKind::Loop { times, body } => {
                 let count = match self.eval(times)? {
                     Value::Num(n) => n,
                     _ => return Err("loop expects number".into()),
                 };
 
                 for _ in 0..count {
-                    for stmt in body.iter() {
-                        match self.exec(stmt)? {
-                            Flow::None => {}
-                            Flow::Continue => break,
-                            Flow::Break => return Ok(Flow::None),
-                            Flow::Return(v) => return Ok(Flow::Return(v)),
-                        }
+                    match self.exec_block(body)? {
+                        Flow::None | Flow::Continue => {}
+                        Flow::Break => return Ok(Flow::None),
+                        Flow::Return(v) => return Ok(Flow::Return(v)),
                     }
                 }
             }
 
-            Node::While { cond, body } => {
+            NodeKind::While { cond, body } => {
                 while matches!(self.eval(cond)?, Value::Bool(true)) {
-                    for stmt in body.iter() {
-                        match self.exec(stmt)? {
-                            Flow::None => {}
-                            Flow::Continue => break,
-                            Flow::Break => return Ok(Flow::None),
-                            Flow::Return(v) => return Ok(Flow::Return(v)),
-                        }
+                    match self.exec_block(body)? {
+                        Flow::None | Flow::Continue => {}
+                        Flow::Break => return Ok(Flow::None),
+                        Flow::Return(v) => return Ok(Flow::Return(v)),
                     }
                 }
             }
 
-            Node::If {
+            NodeKind::For { var, iter, body } => {
+                // `for i in range(...)` counts without building the list
+                if let ExprKind::Call { name, args } = &iter.kind
+                    && name == "range"
+                {
+                    let vals = args
+                        .iter()
+                        .map(|a| self.eval(a))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    let (start, end, step) = range::bounds(&vals)?;
+                    let items = range::steps(start, end, step).map(Value::Num);
+                    return self.exec_for(var, items, body);
+                }
+
+                let items = match self.eval(iter)? {
+                    Value::List(items) => items.lock().unwrap().clone(),
+                    Value::Map(entries) => entries
+                        .lock()
+                        .unwrap()
+                        .keys()
+                        .cloned()
+                        .map(Value::Str)
+                        .collect(),
+                    Value::Str(s) => s.chars().map(|c| Value::Str(c.to_string())).collect(),
+                    _ => return Err("for expects a list, map or string".into()),
+                };
+                return self.exec_for(var, items.into_iter(), body);
+            }
+
+            NodeKind::If {
                 cond,
                 then_body,
                 else_body,
@@ -339,10 +664,25 @@ impl Context {
                 };
 
                 if let Some(stmts) = branch {
-                    for stmt in stmts.iter() {
-                        match self.exec(stmt)? {
-                            Flow::None => {}
-                            f => return Ok(f),
+                    return self.exec_block(stmts);
+                }
+            }
+
+            // settings only change how the file was parsed
+            NodeKind::Use => {}
+
+            NodeKind::Import { path, alias } => {
+                let id = self.modules[self.module].imports[path];
+                let ns = self.import(id, span)?;
+                self.scope.declare(alias, ns);
+            }
+
+            NodeKind::Match { subject, arms } => {
+                let v = self.eval(subject)?;
+                for (patterns, body) in arms {
+                    for p in patterns {
+                        if self.pattern_matches(p, &v)? {
+                            return self.exec_block(body);
                         }
                     }
                 }
@@ -352,117 +692,1183 @@ impl Context {
         Ok(Flow::None)
     }
 
-    fn eval(&self, e: &Expr) -> Result<Value, String> {
+    fn eval(&self, e: &Expr) -> Result<Value, Error> {
+        self.eval_kind(&e.kind, e.span)
+            .map_err(|err| err.at(e.span, self))
+    }
+
+    fn eval_kind(&self, e: &ExprKind, span: Span) -> Result<Value, Error> {
         match e {
-            Expr::Number(n) => Ok(Value::Num(*n)),
-            Expr::Str(s) => Ok(Value::Str(s.clone())),
-            Expr::Bool(b) => Ok(Value::Bool(*b)),
+            ExprKind::Number(n) => Ok(Value::Num(*n)),
+            ExprKind::Float(n) => Ok(Value::Float(*n)),
+            ExprKind::Str(s) => Ok(Value::Str(s.clone())),
+            ExprKind::Bool(b) => Ok(Value::Bool(*b)),
 
-            Expr::Var(name) => {
-                if let Some(v) = self.vars.lock().unwrap().get(name).cloned() {
+            ExprKind::Var(name) => {
+                if let Some(v) = self.scope.get(name) {
                     Ok(v)
                 } else {
                     Ok(Value::Symbol(name.clone()))
                 }
             }
 
-            Expr::Call { name, args } => {
-                let vals = args
+            ExprKind::List(items) => {
+                let vals = items
                     .iter()
-                    .map(|a| self.eval(a))
+                    .map(|i| self.eval(i))
                     .collect::<Result<Vec<_>, _>>()?;
+                Ok(Value::list(vals))
+            }
 
-                if let Some(cmd) = self.cmds.get(name) {
-                    Ok(cmd(vals))
-                } else {
-                    let f = self
-                        .funcs
-                        .lock()
-                        .unwrap()
-                        .get(name)
-                        .cloned()
-                        .ok_or(format!("undefined function {}", name))?;
+            ExprKind::Map(entries) => {
+                let mut map = BTreeMap::new();
+                for (k, v) in entries.iter() {
+                    let k = map_key(&self.eval(k)?)?;
+                    map.insert(k, self.eval(v)?);
+                }
+                Ok(Value::map(map))
+            }
 
-                    let (params, body) = match f {
-                        Node::Function { params, body, .. } => (params, body),
-                        _ => unreachable!(),
-                    };
+            ExprKind::Index(target, index) => {
+                let target = self.eval(target)?;
+                let index = self.eval(index)?;
 
-                    let mut locals = HashMap::new();
-                    for (i, (p, def)) in params.iter().enumerate() {
-                        let v = if let Some(a) = args.get(i) {
-                            self.eval(a)?
-                        } else if let Some(d) = def {
-                            self.eval(d)?
-                        } else {
-                            return Err(format!("missing argument {}", p));
-                        };
-                        locals.insert(p.clone(), v);
+                match target {
+                    Value::List(items) => {
+                        let items = items.lock().unwrap();
+                        let i = list_index(&index, items.len())?;
+                        Ok(items[i].clone())
                     }
-
-                    let saved = self.vars.lock().unwrap().clone();
-                    *self.vars.lock().unwrap() = locals;
-
-                    let mut ret = Value::Bool(false);
-                    for stmt in body.iter() {
-                        match self.exec(stmt)? {
-                            Flow::Return(v) => {
-                                ret = v;
-                                break;
-                            }
-                            Flow::None => {}
-                            _ => return Err("invalid control flow in function".into()),
+                    Value::Map(entries) => {
+                        let key = map_key(&index)?;
+                        match entries.lock().unwrap().get(&key) {
+                            Some(v) => Ok(v.clone()),
+                            None => Err(format!("key \"{}\" not found in map", key).into()),
                         }
                     }
+                    _ => Err("only lists and maps can be indexed".into()),
+                }
+            }
+
+            ExprKind::Field(target, field) => get_field(&self.eval(target)?, field),
+
+            ExprKind::Async(body) => Ok(Value::Task(self.spawn(body))),
+
+            ExprKind::Call { name, args } => {
+                let vals = args
+                    .iter()
+                    .map(|a| self.eval(a))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                self.call_named(name, vals, span)
+            }
 
-                    *self.vars.lock().unwrap() = saved;
-                    Ok(ret)
+            ExprKind::Apply { callee, args } => {
+                let callee = self.eval(callee)?;
+                let vals = args
+                    .iter()
+                    .map(|a| self.eval(a))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                match callee {
+                    Value::Func(f) => self.call_function(&f, vals, span),
+                    Value::Type(t) => construct(&t, vals),
+                    // builtins are not variables, so `let p = print` holds the symbol
+                    Value::Symbol(name) if self.cmds.contains_key(&name) => {
+                        self.call_named(&name, vals, span)
+                    }
+                    _ => Err("value is not a function".into()),
                 }
             }
 
-            Expr::Unary(op, e) => {
+            ExprKind::Lambda { params, body } => Ok(Value::Func(Arc::new(Function {
+                name: "fn".into(),
+                params: params.clone(),
+                body: body.clone(),
+                env: self.scope.clone(),
+                module: self.module,
+            }))),
+
+            ExprKind::Unary(op, e) => {
                 let v = self.eval(e)?;
                 match (op, v) {
-                    (Op::Sub, Value::Num(n)) => Ok(Value::Num(-n)),
+                    (Op::Sub, Value::Num(n)) => match n.checked_neg() {
+                        Some(n) => Ok(Value::Num(n)),
+                        None => Err("integer overflow".into()),
+                    },
+                    (Op::Sub, Value::Float(n)) => Ok(Value::Float(-n)),
                     (Op::Not, Value::Bool(b)) => Ok(Value::Bool(!b)),
                     _ => Err("invalid unary op".into()),
                 }
             }
 
-            Expr::Binary(a, op, b) => {
+            ExprKind::Binary(a, op, b) => {
                 let l = self.eval(a)?;
                 let r = self.eval(b)?;
 
-                match (l, r, op) {
-                    (Value::Num(x), Value::Num(y), Op::Add) => Ok(Value::Num(x + y)),
-                    (Value::Str(x), y, Op::Add) => Ok(Value::Str(x + &format_value(&y))),
-                    (x, Value::Str(y), Op::Add) => Ok(Value::Str(format_value(&x) + &y)),
-                    (Value::Num(x), Value::Num(y), Op::Sub) => Ok(Value::Num(x - y)),
-                    (Value::Num(x), Value::Num(y), Op::Mul) => Ok(Value::Num(x * y)),
-                    (Value::Num(x), Value::Num(y), Op::Div) => Ok(Value::Num(x / y)),
-                    (Value::Num(x), Value::Num(y), Op::Eq) => Ok(Value::Bool(x == y)),
-                    (Value::Num(x), Value::Num(y), Op::Ne) => Ok(Value::Bool(x != y)),
-                    (Value::Num(x), Value::Num(y), Op::Gt) => Ok(Value::Bool(x > y)),
-                    (Value::Num(x), Value::Num(y), Op::Lt) => Ok(Value::Bool(x < y)),
-                    (Value::Num(x), Value::Num(y), Op::Ge) => Ok(Value::Bool(x >= y)),
-                    (Value::Num(x), Value::Num(y), Op::Le) => Ok(Value::Bool(x <= y)),
-                    (Value::Bool(x), Value::Bool(y), Op::Eq) => Ok(Value::Bool(x == y)),
-                    (Value::Bool(x), Value::Bool(y), Op::Ne) => Ok(Value::Bool(x != y)),
-                    (Value::Bool(x), Value::Bool(y), Op::And) => Ok(Value::Bool(x && y)),
-                    (Value::Bool(x), Value::Bool(y), Op::Or) => Ok(Value::Bool(x || y)),
-                    _ => Err("type error".into()),
+                binary(l, *op, r)
+            }
+
+            ExprKind::Cond(cond, then, otherwise) => match self.eval(cond)? {
+                Value::Bool(true) => self.eval(then),
+                Value::Bool(false) => self.eval(otherwise),
+                _ => Err("condition must be boolean".into()),
+            },
+
+            ExprKind::Truthy(e) => Ok(Value::Bool(truthy(&self.eval(e)?)?)),
+        }
+    }
+}
+
+pub fn list_index(index: &Value, len: usize) -> Result<usize, String> {
+    match index {
+        Value::Num(i) if *i >= 0 && (*i as usize) < len => Ok(*i as usize),
+        Value::Num(i) => Err(format!(
+            "index {} out of range for list of length {}",
+            i, len
+        )),
+        _ => Err("list index must be an integer".into()),
+    }
+}
+
+pub fn map_key(key: &Value) -> Result<String, String> {
+    match key {
+        Value::Str(s) => Ok(s.clone()),
+        _ => Err("map keys must be strings".into()),
+    }
+}
+
+pub fn values_equal(a: &Value, b: &Value) -> bool {
+    equal_in(a, b, &mut Vec::new())
+}
+
+// The pairs of lists, maps and structs being compared further up are in
+// `comparing`; meeting one again means a value contains itself, and so far
+// nothing differs.
+fn equal_in(a: &Value, b: &Value, comparing: &mut Vec<(usize, usize)>) -> bool {
+    let pair = match (a, b) {
+        (Value::List(x), Value::List(y)) => {
+            Some((Arc::as_ptr(x) as usize, Arc::as_ptr(y) as usize))
+        }
+        (Value::Map(x), Value::Map(y)) => Some((Arc::as_ptr(x) as usize, Arc::as_ptr(y) as usize)),
+        (Value::Struct(x), Value::Struct(y)) => {
+            Some((Arc::as_ptr(x) as usize, Arc::as_ptr(y) as usize))
+        }
+        _ => None,
+    };
+    if let Some(pair) = pair {
+        if comparing.contains(&pair) {
+            return true;
+        }
+        comparing.push(pair);
+        let equal = compare(a, b, comparing);
+        comparing.pop();
+        return equal;
+    }
+    compare(a, b, comparing)
+}
+
+fn compare(a: &Value, b: &Value, comparing: &mut Vec<(usize, usize)>) -> bool {
+    match (a, b) {
+        (Value::List(x), Value::List(y)) => {
+            if Arc::ptr_eq(x, y) {
+                return true;
+            }
+            let x = x.lock().unwrap().clone();
+            let y = y.lock().unwrap().clone();
+            x.len() == y.len()
+                && x.iter()
+                    .zip(y.iter())
+                    .all(|(a, b)| equal_in(a, b, comparing))
+        }
+        (Value::Map(x), Value::Map(y)) => {
+            if Arc::ptr_eq(x, y) {
+                return true;
+            }
+            let x = x.lock().unwrap().clone();
+            let y = y.lock().unwrap().clone();
+            x.len() == y.len()
+                && x.iter()
+                    .zip(y.iter())
+                    .all(|((ka, a), (kb, b))| ka == kb && equal_in(a, b, comparing))
+        }
+        (Value::Str(x), Value::Str(y)) | (Value::Symbol(x), Value::Symbol(y)) => x == y,
+        (Value::Bool(x), Value::Bool(y)) => x == y,
+        (Value::Func(x), Value::Func(y)) => Arc::ptr_eq(x, y),
+        (Value::Module(x), Value::Module(y)) => Arc::ptr_eq(x, y),
+        (Value::Type(x), Value::Type(y)) => Arc::ptr_eq(x, y),
+        (Value::Task(x), Value::Task(y)) => Arc::ptr_eq(x, y),
+        (Value::Sender(x), Value::Sender(y)) | (Value::Receiver(x), Value::Receiver(y)) => {
+            Arc::ptr_eq(x, y)
+        }
+        (Value::Counter(x), Value::Counter(y)) => Arc::ptr_eq(x, y),
+        // a get_mouse() Point equals one built with a Point declared in the script
+        (Value::Struct(x), Value::Struct(y)) => {
+            if Arc::ptr_eq(x, y) {
+                return true;
+            }
+            let a = x.values.lock().unwrap().clone();
+            let b = y.values.lock().unwrap().clone();
+            x.ty.name == y.ty.name
+                && x.ty.fields == y.ty.fields
+                && a.iter()
+                    .zip(b.iter())
+                    .all(|(a, b)| equal_in(a, b, comparing))
+        }
+        (Value::Num(x), Value::Num(y)) => x == y,
+        (x, y) => match (x.as_f64(), y.as_f64()) {
+            (Some(x), Some(y)) => x == y,
+            _ => false,
+        },
+    }
+}
+
+impl Context {
+    // a range only matches numbers, anything else moves on to the next arm
+    fn pattern_matches(&self, p: &Pattern, v: &Value) -> Result<bool, Error> {
+        match p {
+            Pattern::Wildcard => Ok(true),
+            Pattern::Value(e) => Ok(values_equal(&self.eval(e)?, v)),
+            Pattern::Range {
+                start,
+                end,
+                inclusive,
+            } => {
+                let (start, end) = match (self.eval(start)?.as_f64(), self.eval(end)?.as_f64()) {
+                    (Some(s), Some(e)) => (s, e),
+                    _ => return Err("range pattern bounds must be numbers".into()),
+                };
+                Ok(match v.as_f64() {
+                    Some(x) => start <= x && (x < end || (*inclusive && x == end)),
+                    None => false,
+                })
+            }
+        }
+    }
+
+    fn exec_for(
+        &self,
+        var: &str,
+        items: impl Iterator<Item = Value>,
+        body: &[Node],
+    ) -> Result<Flow, Error> {
+        for item in items {
+            if task::cancelled() {
+                return Err(Error::cancelled());
+            }
+            task::yield_point();
+            let inner = self.child();
+            inner.scope.declare(var, item);
+            match inner.exec_body(body)? {
+                Flow::None | Flow::Continue => {}
+                Flow::Break => return Ok(Flow::None),
+                Flow::Return(v) => return Ok(Flow::Return(v)),
+            }
+        }
+        Ok(Flow::None)
+    }
+
+    // Waits until one of the arms is ready and runs it; when several are,
+    // the first one written wins.
+    fn select(&self, arms: &[(SelectArm, Vec<Node>)]) -> Result<Flow, Error> {
+        enum Source {
+            Recv(Arc<Channel>),
+            Key(String),
+            // None when it is too far off to ever come
+            Timeout(Option<Instant>),
+        }
+
+        let start = Instant::now();
+        let mut sources = Vec::new();
+        for (arm, _) in arms {
+            sources.push(match arm {
+                SelectArm::Recv { from, .. } => match self.eval(from)? {
+                    Value::Receiver(c) => Source::Recv(c),
+                    _ => return Err("select can only recv from a receiver".into()),
+                },
+                SelectArm::Key(key) => Source::Key(key_name(self.eval(key)?)?),
+                SelectArm::Timeout(ms) => match self.eval(ms)?.as_f64() {
+                    Some(ms) if ms >= 0.0 => {
+                        Source::Timeout(start.checked_add(millis("select timeout", ms)?))
+                    }
+                    _ => return Err("select timeout expects a number of milliseconds".into()),
+                },
+            });
+        }
+        let keys = sources.iter().any(|s| matches!(s, Source::Key(_)));
+        let deadline = sources
+            .iter()
+            .filter_map(|s| match s {
+                Source::Timeout(at) => *at,
+                _ => None,
+            })
+            .min();
+
+        loop {
+            // watched before anything is checked, so nothing that happens
+            // in between is missed
+            let waker = task::waker();
+            for s in &sources {
+                if let Source::Recv(c) = s {
+                    c.watch(waker.clone());
                 }
             }
+            let input = if keys {
+                Some(input::watch(waker)?)
+            } else {
+                None
+            };
+
+            let mut closed = 0;
+            for (i, source) in sources.iter().enumerate() {
+                let received = match source {
+                    Source::Recv(c) => match c.try_recv() {
+                        Recv::Value(v) => Some(v),
+                        Recv::Closed => {
+                            closed += 1;
+                            None
+                        }
+                        _ => None,
+                    },
+                    Source::Key(key) => input
+                        .as_ref()
+                        .is_some_and(|i| i.pressed(key))
+                        .then_some(Value::Bool(true)),
+                    Source::Timeout(at) => at
+                        .is_some_and(|at| Instant::now() >= at)
+                        .then_some(Value::Bool(false)),
+                };
+                let Some(v) = received else {
+                    continue;
+                };
+
+                let (arm, body) = &arms[i];
+                let inner = self.child();
+                if let SelectArm::Recv {
+                    var: Some(name), ..
+                } = arm
+                {
+                    inner.scope.declare(name, v);
+                }
+                return inner.exec_body(body);
+            }
+
+            if closed == sources.len() {
+                return Err("select over channels that are all closed".into());
+            }
+            // another task failed, the script is stopping
+            if self.error.lock().unwrap().is_some() {
+                return Ok(Flow::None);
+            }
+            task::wait(deadline);
+            if task::cancelled() {
+                return Err(Error::cancelled());
+            }
+        }
+    }
+
+    // Runs the block as a task of its own. An error in it stops the whole
+    // script like an error anywhere else; `return v` gives the task's result.
+    fn spawn(&self, body: &[Node]) -> Arc<Task> {
+        let ctx = self.child();
+        let body = body.to_vec();
+        let task = Task::new();
+        let t = task.clone();
+
+        task.start(move || {
+            let state = match ctx.exec_body(&body) {
+                Ok(Flow::Return(v)) => State::Done(v),
+                Ok(Flow::None) => State::Done(Value::Bool(false)),
+                Ok(Flow::Break | Flow::Continue) => {
+                    *ctx.error.lock().unwrap() = Some("break outside loop".into());
+                    State::Failed("break outside loop".into())
+                }
+                Err(e) if e.cancelled => State::Cancelled,
+                Err(e) => {
+                    *ctx.error.lock().unwrap() = Some(e.clone());
+                    task::stop();
+                    State::Failed(e)
+                }
+            };
+            t.finish(state);
+        });
+
+        self.tasks.lock().unwrap().push(task.clone());
+        task
+    }
+
+    // runs an imported file the first time it is imported, with globals of its own
+    fn import(&self, id: usize, span: Span) -> Result<Value, Error> {
+        if let Some(ns) = self.namespaces.lock().unwrap().get(&id) {
+            return Ok(ns.clone());
+        }
+
+        let m = &self.modules[id];
+        let ctx = Context {
+            scope: globals(),
+            frames: Some(Arc::new(Frame {
+                name: m.file.clone(),
+                call: span,
+                module: self.module,
+                parent: self.frames.clone(),
+            })),
+            module: id,
+            ..self.clone()
+        };
+        ctx.run(&m.ast)?;
+
+        let ns = Value::Module(Arc::new(Namespace {
+            file: m.file.clone(),
+            scope: ctx.scope.clone(),
+        }));
+        self.namespaces.lock().unwrap().insert(id, ns.clone());
+        Ok(ns)
+    }
+
+    fn call_named(&self, name: &str, vals: Vec<Value>, span: Span) -> Result<Value, Error> {
+        // errors reported by builtins are thrown like any other runtime error
+        if let Some(cmd) = self.cmds.get(name) {
+            return match cmd(vals) {
+                Value::Error(e) => Err(e.into()),
+                Value::Thrown(e) => Err(*e),
+                // sleep and wait return early when the task is cancelled
+                _ if task::cancelled() => Err(Error::cancelled()),
+                v => Ok(v),
+            };
+        }
+
+        match self.scope.get(name) {
+            Some(Value::Func(f)) => self.call_function(&f, vals, span),
+            Some(Value::Type(t)) => construct(&t, vals),
+            // a builtin passed in a variable or as an argument
+            Some(Value::Symbol(s)) if self.cmds.contains_key(&s) => self.call_named(&s, vals, span),
+            Some(_) => Err(format!("{} is not a function", name).into()),
+            None => Err(format!("undefined function {}", name).into()),
+        }
+    }
+
+    fn call_function(&self, f: &Function, vals: Vec<Value>, span: Span) -> Result<Value, Error> {
+        // the body runs on top of the defining scope, not the caller's
+        let frame = Context {
+            scope: Scope::new(Some(f.env.clone())),
+            frames: Some(Arc::new(Frame {
+                name: f.name.clone(),
+                call: span,
+                module: self.module,
+                parent: self.frames.clone(),
+            })),
+            module: f.module,
+            ..self.clone()
+        };
+
+        let mut vals = vals.into_iter();
+        for (p, def) in f.params.iter() {
+            let v = match (vals.next(), def) {
+                (Some(v), _) => v,
+                (None, Some(d)) => frame.eval(d)?,
+                (None, None) => return Err(format!("missing argument {}", p).into()),
+            };
+            frame.scope.declare(p, v);
+        }
+
+        match frame.exec_body(&f.body)? {
+            Flow::Return(v) => Ok(v),
+            Flow::None => Ok(Value::Bool(false)),
+            _ => Err("invalid control flow in function".into()),
         }
     }
 }
 
-fn format_value(v: &Value) -> String {
+// values compared with values_equal rather than by the arms below
+fn is_compound(v: &Value) -> bool {
+    matches!(
+        v,
+        Value::List(_)
+            | Value::Map(_)
+            | Value::Func(_)
+            | Value::Module(_)
+            | Value::Struct(_)
+            | Value::Type(_)
+            | Value::Task(_)
+            | Value::Sender(_)
+            | Value::Receiver(_)
+            | Value::Counter(_)
+    )
+}
+
+// `op=` on a list element, map entry or struct field, done so that tasks
+// updating the same one together never lose an update. Plain values are
+// combined while the slot stays locked. A list, map or struct operand may
+// hold the very list being updated, so that is combined without the lock and
+// only written back if no other task changed the slot meanwhile.
+fn update_slot<T>(
+    lock: &Mutex<T>,
+    slot: impl Fn(&mut T) -> Result<&mut Value, Error>,
+    op: Op,
+    v: Value,
+) -> Result<(), Error> {
+    loop {
+        let mut guard = lock.lock().unwrap();
+        let old = slot(&mut guard)?;
+        if !is_compound(old) && !is_compound(&v) {
+            *old = binary(old.clone(), op, v)?;
+            return Ok(());
+        }
+        let before = old.clone();
+        drop(guard);
+
+        let new = binary(before.clone(), op, v.clone())?;
+        let mut guard = lock.lock().unwrap();
+        let old = slot(&mut guard)?;
+        if same_value(old, &before) {
+            *old = new;
+            return Ok(());
+        }
+    }
+}
+
+// whether a slot still holds what was read from it: the very same list, map
+// or struct, or an equal plain value
+fn same_value(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::List(x), Value::List(y)) => Arc::ptr_eq(x, y),
+        (Value::Map(x), Value::Map(y)) => Arc::ptr_eq(x, y),
+        (Value::Struct(x), Value::Struct(y)) => Arc::ptr_eq(x, y),
+        (Value::List(_) | Value::Map(_) | Value::Struct(_), _)
+        | (_, Value::List(_) | Value::Map(_) | Value::Struct(_)) => false,
+        _ => values_equal(a, b),
+    }
+}
+
+// Point(1, 2) fills the fields in the order they were declared
+fn construct(t: &Arc<StructType>, vals: Vec<Value>) -> Result<Value, Error> {
+    if vals.len() != t.fields.len() {
+        return Err(format!(
+            "{} expects {} argument(s) ({}), got {}",
+            t.name,
+            t.fields.len(),
+            t.fields.join(", "),
+            vals.len()
+        )
+        .into());
+    }
+    Ok(Value::Struct(Arc::new(Instance {
+        ty: t.clone(),
+        values: Mutex::new(vals),
+    })))
+}
+
+// `p.x` reads a struct field, `h.move` a variable of the file imported as `h`.
+// A bare word stays a bare word, so `process(notepad.exe)` still works.
+fn get_field(target: &Value, field: &str) -> Result<Value, Error> {
+    match target {
+        Value::Struct(s) => s
+            .get(field)
+            .ok_or_else(|| format!("{} has no field '{}'", s.ty.name, field).into()),
+        Value::Module(m) => m
+            .scope
+            .get(field)
+            .ok_or_else(|| format!("{} has no variable '{}'", m.file, field).into()),
+        Value::Symbol(s) => Ok(Value::Symbol(format!("{}.{}", s, field))),
+        _ => Err("only structs and imported files have fields".into()),
+    }
+}
+
+fn set_field(target: &Value, field: &str, v: Value) -> Result<(), Error> {
+    match target {
+        Value::Struct(s) => {
+            if s.set(field, v) {
+                Ok(())
+            } else {
+                Err(format!("{} has no field '{}'", s.ty.name, field).into())
+            }
+        }
+        Value::Module(m) if m.scope.get(field).is_some() => Ok(m.scope.assign(field, v)?),
+        Value::Module(m) => Err(format!("{} has no variable '{}'", m.file, field).into()),
+        _ => Err("only structs and imported files have fields".into()),
+    }
+}
+
+fn key_name(v: Value) -> Result<String, Error> {
+    match v {
+        Value::Symbol(s) | Value::Str(s) => Ok(s),
+        Value::Num(n) => Ok(n.to_string()),
+        _ => Err("key must be a symbol, string, or a number".into()),
+    }
+}
+
+// The rule behind `use truthy`: false, 0, 0.0, NaN, "" and empty lists and
+// maps are false, everything else is true. An undefined name is an error
+// since it is almost always a typo.
+fn truthy(v: &Value) -> Result<bool, Error> {
+    Ok(match v {
+        Value::Bool(b) => *b,
+        Value::Num(n) => *n != 0,
+        Value::Float(f) => *f != 0.0 && !f.is_nan(),
+        Value::Str(s) => !s.is_empty(),
+        Value::List(items) => !items.lock().unwrap().is_empty(),
+        Value::Map(entries) => !entries.lock().unwrap().is_empty(),
+        Value::Func(_)
+        | Value::Module(_)
+        | Value::Struct(_)
+        | Value::Type(_)
+        | Value::Task(_)
+        | Value::Sender(_)
+        | Value::Receiver(_)
+        | Value::Counter(_) => true,
+        Value::Symbol(s) => return Err(format!("'{}' is not defined", s).into()),
+        Value::Error(e) => return Err(e.clone().into()),
+        Value::Thrown(e) => return Err((**e).clone()),
+    })
+}
+
+fn binary(l: Value, op: Op, r: Value) -> Result<Value, Error> {
+    match (l, r, op) {
+        (
+            Value::Num(x),
+            Value::Num(y),
+            Op::Add | Op::Sub | Op::Mul | Op::Div | Op::Mod | Op::Pow,
+        ) => int_binary(x, op, y),
+        (Value::List(x), Value::List(y), Op::Add) => {
+            let mut items = x.lock().unwrap().clone();
+            items.extend(y.lock().unwrap().iter().cloned());
+            Ok(Value::list(items))
+        }
+        // values of different types are never equal, 1 == 1.0 is
+        (x, y, Op::Eq | Op::Ne) => Ok(Value::Bool(values_equal(&x, &y) == matches!(op, Op::Eq))),
+        (Value::Str(x), y, Op::Add) => Ok(Value::Str(x + &format_value(&y))),
+        (x, Value::Str(y), Op::Add) => Ok(Value::Str(format_value(&x) + &y)),
+        (Value::Num(x), Value::Num(y), Op::Gt) => Ok(Value::Bool(x > y)),
+        (Value::Num(x), Value::Num(y), Op::Lt) => Ok(Value::Bool(x < y)),
+        (Value::Num(x), Value::Num(y), Op::Ge) => Ok(Value::Bool(x >= y)),
+        (Value::Num(x), Value::Num(y), Op::Le) => Ok(Value::Bool(x <= y)),
+        (Value::Bool(x), Value::Bool(y), Op::And) => Ok(Value::Bool(x && y)),
+        (Value::Bool(x), Value::Bool(y), Op::Or) => Ok(Value::Bool(x || y)),
+        (x, y, _) => match (x.as_f64(), y.as_f64()) {
+            (Some(x), Some(y)) => float_binary(x, op, y),
+            _ => Err("type error".into()),
+        },
+    }
+}
+
+// integer results that don't fit and division by zero are errors rather than panics;
+// % never returns a negative number, so -1 % 4 is 3
+fn int_binary(x: i64, op: Op, y: i64) -> Result<Value, Error> {
+    let v = match op {
+        Op::Add => x.checked_add(y),
+        Op::Sub => x.checked_sub(y),
+        Op::Mul => x.checked_mul(y),
+        Op::Div | Op::Mod if y == 0 => return Err("division by zero".into()),
+        Op::Div => x.checked_div(y),
+        Op::Mod => x.checked_rem_euclid(y),
+        Op::Pow => return Ok(power(&Value::Num(x), &Value::Num(y))?),
+        _ => return Err("type error".into()),
+    };
+    v.map(Value::Num).ok_or_else(|| "integer overflow".into())
+}
+
+// x ** y stays an integer for an integer base and a non-negative integer exponent
+pub fn power(x: &Value, y: &Value) -> Result<Value, String> {
+    if let (Value::Num(x), Value::Num(y)) = (x, y)
+        && *y >= 0
+    {
+        return u32::try_from(*y)
+            .ok()
+            .and_then(|y| x.checked_pow(y))
+            .map(Value::Num)
+            .ok_or_else(|| "integer overflow".into());
+    }
+    match (x.as_f64(), y.as_f64()) {
+        (Some(x), Some(y)) => Ok(Value::Float(x.powf(y))),
+        _ => Err("type error".into()),
+    }
+}
+
+// Any float operand promotes the whole operation to float.
+fn float_binary(x: f64, op: Op, y: f64) -> Result<Value, Error> {
+    Ok(match op {
+        Op::Add => Value::Float(x + y),
+        Op::Sub => Value::Float(x - y),
+        Op::Mul => Value::Float(x * y),
+        Op::Div | Op::Mod if y == 0.0 => return Err("division by zero".into()),
+        Op::Div => Value::Float(x / y),
+        Op::Mod => Value::Float(x.rem_euclid(y)),
+        Op::Pow => Value::Float(x.powf(y)),
+        Op::Eq => Value::Bool(x == y),
+        Op::Ne => Value::Bool(x != y),
+        Op::Gt => Value::Bool(x > y),
+        Op::Lt => Value::Bool(x < y),
+        Op::Ge => Value::Bool(x >= y),
+        Op::Le => Value::Bool(x <= y),
+        _ => return Err("type error".into()),
+    })
+}
+
+pub fn format_value(v: &Value) -> String {
+    format_in(v, &mut Vec::new())
+}
+
+// `printing` holds the lists, maps and structs being printed further up; one
+// that contains itself shows as [...], {...} or Name { ... } the second time.
+fn format_in(v: &Value, printing: &mut Vec<usize>) -> String {
+    let id = match v {
+        Value::List(items) => Some(Arc::as_ptr(items) as usize),
+        Value::Map(entries) => Some(Arc::as_ptr(entries) as usize),
+        Value::Struct(s) => Some(Arc::as_ptr(s) as usize),
+        _ => None,
+    };
+    let Some(id) = id else {
+        return format_plain(v, printing);
+    };
+    if printing.contains(&id) {
+        return match v {
+            Value::List(_) => "[...]".into(),
+            Value::Map(_) => "{...}".into(),
+            Value::Struct(s) => format!("{} {{ ... }}", s.ty.name),
+            _ => unreachable!(),
+        };
+    }
+    printing.push(id);
+    let text = format_plain(v, printing);
+    printing.pop();
+    text
+}
+
+fn format_plain(v: &Value, printing: &mut Vec<usize>) -> String {
     match v {
         Value::Num(n) => n.to_string(),
+        Value::Float(f) if f.is_finite() && f.fract() == 0.0 => format!("{:.1}", f),
+        Value::Float(f) => f.to_string(),
         Value::Str(s) => s.clone(),
         Value::Bool(b) => b.to_string(),
         Value::Symbol(s) => s.clone(),
+        Value::List(items) => {
+            let items = items.lock().unwrap().clone();
+            let items: Vec<String> = items.iter().map(|v| format_item(v, printing)).collect();
+            format!("[{}]", items.join(", "))
+        }
+        Value::Map(entries) => {
+            let entries = entries.lock().unwrap().clone();
+            let entries: Vec<String> = entries
+                .iter()
+                .map(|(k, v)| format!("\"{}\": {}", k, format_item(v, printing)))
+                .collect();
+            format!("{{{}}}", entries.join(", "))
+        }
+        Value::Func(f) => format!("<fn {}>", f.name),
+        Value::Module(m) => format!("<module {}>", m.file),
+        Value::Struct(s) => {
+            let values = s.values.lock().unwrap().clone();
+            let fields: Vec<String> =
+                s.ty.fields
+                    .iter()
+                    .zip(values.iter())
+                    .map(|(f, v)| format!("{}: {}", f, format_item(v, printing)))
+                    .collect();
+            format!("{} {{ {} }}", s.ty.name, fields.join(", "))
+        }
+        Value::Type(t) => format!("<struct {}>", t.name),
+        Value::Task(t) => match t.state() {
+            State::Running => "<task running>".into(),
+            State::Done(_) => "<task done>".into(),
+            State::Failed(_) => "<task failed>".into(),
+            State::Cancelled => "<task cancelled>".into(),
+        },
+        Value::Sender(_) => "<sender>".into(),
+        Value::Receiver(_) => "<receiver>".into(),
+        Value::Counter(c) => format!("<counter {}>", c.load(Ordering::SeqCst)),
         Value::Error(e) => e.clone(),
+        Value::Thrown(e) => format_in(&e.value, printing),
+    }
+}
+
+// strings are quoted when nested inside a collection
+fn format_item(v: &Value, printing: &mut Vec<usize>) -> String {
+    match v {
+        Value::Str(s) => format!("\"{}\"", s),
+        v => format_in(v, printing),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostic::Severity;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    // Runs src as a whole script, waiting for its tasks, and gives back the
+    // variable `out` as print would show it, or the message of the error.
+    fn run(src: &str) -> Result<String, String> {
+        let (ctx, result) = run_script(src);
+        result.map_err(|e| e.message())?;
+        Ok(ctx
+            .scope
+            .get("out")
+            .map_or(String::new(), |v| format_value(&v)))
+    }
+
+    // the error of a failing script as it is shown to the user
+    fn report(src: &str) -> String {
+        let (ctx, result) = run_script(src);
+        result.unwrap_err().render(&ctx.modules)
+    }
+
+    fn run_script(src: &str) -> (Context, Result<(), Error>) {
+        let (tokens, mut diagnostics) = Lexer::new(src).tokenize();
+        let (ast, parse_diagnostics) = Parser::new(tokens).parse();
+        diagnostics.extend(parse_diagnostics);
+        diagnostics.retain(|d| d.severity == Severity::Error);
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+
+        let modules = Arc::new(vec![Module {
+            file: "test.num".into(),
+            src: src.into(),
+            ast,
+            imports: HashMap::new(),
+        }]);
+        let mut builtins = HashMap::new();
+        crate::functions::register_all(&mut builtins);
+        let ctx = Context::new(builtins, modules.clone());
+        let result = ctx.run(&modules[0].ast).and_then(|_| ctx.join_tasks());
+        (ctx, result)
+    }
+
+    #[test]
+    fn functions_read_and_write_globals() {
+        let src = r#"
+            let speed = 5
+            let count = 0
+            def bump(n) { count += n; return speed }
+            let r = bump(2)
+            let seen = count
+            count = 10
+            let f = fn() { return count }
+            count = 11
+            let out = [r, seen, f()]
+        "#;
+        assert_eq!(run(src).unwrap(), "[5, 2, 11]");
+    }
+
+    #[test]
+    fn inner_lets_shadow_without_touching_the_outer_variable() {
+        let src = r#"
+            let x = 1
+            let speed = 5
+            if (true) { let x = 2; x = 3 }
+            for i in [1] { let x = 4 }
+            def shadow(speed) { speed = 9; return speed }
+            let out = [x, shadow(7), speed]
+        "#;
+        assert_eq!(run(src).unwrap(), "[1, 9, 5]");
+    }
+
+    #[test]
+    fn tasks_keep_their_own_locals_while_functions_run() {
+        let src = r#"
+            def work(a) { let local = a * 2; sleep(1); return local }
+            let t = async { let mine = "task"; work(1); return mine }
+            let out = [work(5), wait(t)]
+        "#;
+        assert_eq!(run(src).unwrap(), "[10, \"task\"]");
+    }
+
+    #[test]
+    fn runtime_errors_point_at_the_expression_and_list_the_calls() {
+        let src = "\
+def inner(a) {
+  return a + nope(1)
+}
+def outer() { return inner(1) }
+outer()";
+        assert_eq!(
+            report(src),
+            "\
+Runtime error: undefined function nope
+ --> test.num:2:14
+  |
+2 |   return a + nope(1)
+  |              ^
+  = in inner, called at test.num:4:22
+  = in outer, called at test.num:5:1
+"
+        );
+    }
+
+    #[test]
+    fn an_error_is_located_at_the_innermost_operation() {
+        let src = "let a = [1, 2]\nlet b = 1 + (2 * -a)";
+        assert!(report(src).contains(" --> test.num:2:18\n"));
+        let src = r#"
+            let out = 0
+            try { let x = 1
+                  x = x / 0 } catch (e) { out = [e["line"], e["col"], e["message"]] }
+        "#;
+        assert_eq!(run(src).unwrap(), r#"[4, 25, "division by zero"]"#);
+    }
+
+    #[test]
+    fn modulo_and_power() {
+        let src =
+            "let out = [7 % 3, -7 % 3, 7.5 % 2, 2 ** 10, 2 ** -1, 4 ** 0.5, -2 ** 2, 2 ** 3 ** 2]";
+        assert_eq!(run(src).unwrap(), "[1, 2, 1.5, 1024, 0.5, 2.0, -4, 512]");
+        assert_eq!(run("let out = 2 ** 63").unwrap_err(), "integer overflow");
+    }
+
+    #[test]
+    fn dividing_by_zero_is_an_error() {
+        for src in ["1 / 0", "1 % 0", "1.0 / 0", "5 % 0.0", "1 / 0.0"] {
+            let src = format!("let out = {}", src);
+            assert_eq!(run(&src).unwrap_err(), "division by zero", "{}", src);
+        }
+        let src = "let a = 5\na /= 0";
+        assert_eq!(run(src).unwrap_err(), "division by zero");
+    }
+
+    #[test]
+    fn a_parameter_may_hide_a_constant() {
+        let src = r#"
+            const speed = 5
+            def scaled(speed) { speed *= 2; return speed }
+            def base() { return speed }
+            let out = [scaled(3), base(), speed]
+        "#;
+        assert_eq!(run(src).unwrap(), "[6, 5, 5]");
+    }
+
+    #[test]
+    fn structs_with_the_same_name_and_equal_fields_are_equal() {
+        let src = r#"
+            struct Other { x, y }
+            let a = Point(1, 2)
+            let b = Point(1, 2.0)
+            let out = [a == b, a != Point(1, 3), a == Other(1, 2), a == [1, 2], a == a]
+        "#;
+        assert_eq!(run(src).unwrap(), "[true, true, false, false, true]");
+    }
+
+    #[test]
+    fn struct_fields_are_fixed() {
+        let src = r#"
+            struct Rect { pos, size }
+            let r = Rect(Point(1, 2), Point(3, 4))
+            r.pos.x += 4
+            r.size = 0
+            let out = r
+        "#;
+        assert_eq!(
+            run(src).unwrap(),
+            "Rect { pos: Point { x: 5, y: 2 }, size: 0 }"
+        );
+        let src = "let p = Point(1)";
+        assert_eq!(
+            run(src).unwrap_err(),
+            "Point expects 2 argument(s) (x, y), got 1"
+        );
+        let src = "let p = Point(1, 2)\np.z = 1";
+        assert_eq!(run(src).unwrap_err(), "Point has no field 'z'");
+        let src = "let p = Point(1, 2)\nlet out = p.z";
+        assert_eq!(run(src).unwrap_err(), "Point has no field 'z'");
+    }
+
+    #[test]
+    fn placeholders_insert_any_expression() {
+        let src = r#"
+            let p = {"x": 3}
+            let out = "{p["x"] * 2} of {len([1, 2])} {{literal}}"
+        "#;
+        assert_eq!(run(src).unwrap(), "6 of 2 {literal}");
+    }
+
+    #[test]
+    fn the_smallest_integer_is_a_literal() {
+        let src =
+            "let out = [-9223372036854775808, 0 - 9223372036854775807 - 1 == -9223372036854775808]";
+        assert_eq!(run(src).unwrap(), "[-9223372036854775808, true]");
+    }
+
+    #[test]
+    fn select_timeout_too_far_off_never_fires() {
+        let src = r#"
+            let c = channel()
+            let out = "nothing"
+            async { sleep(5); send(c[0], "sent") }
+            select {
+                recv(c[1]) as v => { out = v }
+                timeout(10.0 ** 15) => { out = "timeout" }
+            }
+        "#;
+        assert_eq!(run(src).unwrap(), "sent");
+    }
+
+    #[test]
+    fn select_timeout_rejects_times_too_long_for_a_duration() {
+        let src = "select { timeout(10.0 ** 25) => { } }";
+        assert_eq!(run(src).unwrap_err(), "select timeout time is too long");
+        let src = "select { timeout(10.0 ** 400) => { } }";
+        assert!(run(src).is_err());
+    }
+
+    #[test]
+    fn for_over_range_does_not_build_the_list() {
+        let src = r#"
+            let out = 0
+            for i in range(0, 100000000000) {
+                out += 1
+                if (out == 3) { break }
+            }
+        "#;
+        assert_eq!(run(src).unwrap(), "3");
+    }
+
+    #[test]
+    fn range_near_the_largest_integer_stops_short_of_it() {
+        let src = "let out = range(9223372036854775800, 9223372036854775807, 5)";
+        assert_eq!(
+            run(src).unwrap(),
+            "[9223372036854775800, 9223372036854775805]"
+        );
+    }
+
+    #[test]
+    fn catch_gets_the_thrown_value_as_it_was() {
+        let src = r#"
+            let out = 0
+            try { throw({"code": 3}) } catch (e) { out = e["value"]["code"] }
+        "#;
+        assert_eq!(run(src).unwrap(), "3");
+
+        let src = r#"
+            let out = ""
+            try { throw([1, 2]) } catch (e) { out = e["message"] }
+        "#;
+        assert_eq!(run(src).unwrap(), "[1, 2]");
+    }
+
+    #[test]
+    fn errors_from_builtins_carry_their_message_as_the_value() {
+        let src = r#"
+            let out = ""
+            try { range(1, 2, 0) } catch (e) { out = e["value"] }
+        "#;
+        assert_eq!(run(src).unwrap(), "range step cannot be 0");
+    }
+
+    #[test]
+    fn a_float_operand_makes_the_result_a_float() {
+        assert_eq!(run("let out = 1 + 0.5").unwrap(), "1.5");
+        assert_eq!(run("let out = 7 / 2.0").unwrap(), "3.5");
+        assert_eq!(run("let out = 7 / 2").unwrap(), "3");
+    }
+
+    #[test]
+    fn compound_assignment_and_increments() {
+        let src = r#"
+            let a = 10
+            a += 5; a -= 3; a *= 2; a /= 4; a %= 4
+            let b = 1
+            b++; b++; b--
+            let s = "x"
+            s += 1
+            let out = [a, b, s]
+        "#;
+        assert_eq!(run(src).unwrap(), r#"[2, 2, "x1"]"#);
+    }
+
+    #[test]
+    fn elements_and_keys_can_be_assigned() {
+        let src = r#"
+            let l = [1, [2, 3]]
+            l[0] = 9; l[1][0] += 10; l[1][1]++
+            let m = {"a": 1}
+            m["b"] = 2; m["a"] *= 5
+            let out = [l, m]
+        "#;
+        assert_eq!(run(src).unwrap(), r#"[[9, [12, 4]], {"a": 5, "b": 2}]"#);
+        let bad = [
+            (
+                "let l = [1]\nl[5] = 1",
+                "index 5 out of range for list of length 1",
+            ),
+            ("let m = {}\nm[\"z\"] += 1", "key \"z\" not found in map"),
+            ("let n = 1\nn[0] = 1", "only lists and maps can be indexed"),
+        ];
+        for (src, message) in bad {
+            assert_eq!(run(src).unwrap_err(), message);
+        }
+    }
+
+    #[test]
+    fn compound_updates_on_elements_and_fields_are_not_lost() {
+        let src = r#"
+            struct P { x }
+            let xs = [0, []]
+            let m = {"k": 0}
+            let p = P(0)
+            let ts = []
+            loop (8) {
+                push(ts, async {
+                    loop (500) { xs[0] += 1; xs[1] += [1]; m["k"]++; p.x += 1 }
+                })
+            }
+            for t in ts { wait(t) }
+            let out = [xs[0], len(xs[1]), m["k"], p.x]
+        "#;
+        assert_eq!(run(src).unwrap(), "[4000, 4000, 4000, 4000]");
+    }
+
+    #[test]
+    fn compound_update_may_use_the_list_it_changes() {
+        let src = r#"
+            let xs = [[1]]
+            xs[0] += xs
+            let out = len(xs[0])
+        "#;
+        assert_eq!(run(src).unwrap(), "2");
+    }
+
+    #[test]
+    fn busy_loops_in_tasks_leave_room_for_other_tasks() {
+        let src = r#"
+            let stop = false
+            let out = "not yet"
+            loop (8) { async { while (!stop) { } } }
+            async { out = "ran"; stop = true }
+        "#;
+        assert_eq!(run(src).unwrap(), "ran");
+    }
+
+    #[test]
+    fn builtins_can_be_passed_around_like_functions() {
+        let src = r#"
+            let a = abs
+            def apply(f, x) { return f(x) }
+            let out = [a(-2), apply(abs, -3), apply(upper, "x")]
+        "#;
+        assert_eq!(run(src).unwrap(), "[2, 3, \"X\"]");
+    }
+
+    #[test]
+    fn equality_works_the_same_for_every_type() {
+        let src =
+            r#"let out = ["a" == "a", 1 == "1", 1 == 1.0, "a" != "b", ["a"] == ["a"], true != 1]"#;
+        assert_eq!(run(src).unwrap(), "[true, false, true, true, true, true]");
+    }
+
+    #[test]
+    fn lists_and_maps_that_contain_themselves_print_and_compare() {
+        let src = r#"
+            let xs = []
+            push(xs, xs)
+            let ys = []
+            push(ys, ys)
+            let m = {"a": 1}
+            m["self"] = m
+            let out = [xs, m, xs == ys]
+        "#;
+        assert_eq!(
+            run(src).unwrap(),
+            r#"[[[...]], {"a": 1, "self": {...}}, true]"#
+        );
     }
 }
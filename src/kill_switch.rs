@@ -0,0 +1,47 @@
+use crate::functions::release;
+use crate::input;
+use crate::task::{self, Waker};
+use std::borrow::Cow;
+use std::process;
+use std::sync::Mutex;
+use std::thread;
+
+// The keys that stop a running script from anywhere, held down together.
+// Pressing them a second time while the script is still busy stopping quits
+// right away.
+static KEYS: Mutex<Option<Cow<'static, str>>> = Mutex::new(Some(Cow::Borrowed("Ctrl+Alt+Q")));
+
+pub fn set(keys: Option<String>) {
+    *KEYS.lock().unwrap() = keys.map(Cow::Owned);
+}
+
+fn keys() -> Option<String> {
+    KEYS.lock().unwrap().as_deref().map(String::from)
+}
+
+// watches the keys on a thread of its own, so a busy script can't hold it up
+pub fn start() {
+    thread::spawn(|| {
+        let mut pressed_before = false;
+        loop {
+            let Ok(now) = input::watch(Waker::Thread(thread::current())) else {
+                // nothing to watch without a keyboard
+                return;
+            };
+            let pressed = keys().is_some_and(|k| k.split('+').all(|key| now.pressed(key.trim())));
+            if pressed && !pressed_before {
+                if task::stopping() {
+                    release::release_all();
+                    process::exit(1);
+                }
+                println!(
+                    "\nstopped by the kill switch ({})",
+                    keys().unwrap_or_default()
+                );
+                task::stop();
+            }
+            pressed_before = pressed;
+            thread::park();
+        }
+    });
+}
@@ -1,4 +1,10 @@
-use crate::token::{Token, TokenKind};
+use crate::{
+    ast::Span,
+    diagnostic::Diagnostic,
+    token::{TemplatePart, Token, TokenKind},
+};
+
+const ESCAPES: &str = "valid escapes are \\n \\t \\r \\0 \\\" \\\\ \\{ \\} and \\u{...}";
 
 pub struct Lexer {
     src: Vec<char>,
@@ -6,6 +12,7 @@ pub struct Lexer {
     line: usize,
     col: usize,
     last_token: Option<TokenKind>,
+    diagnostics: Vec<Diagnostic>,
 }
 
 impl Lexer {
@@ -16,10 +23,13 @@ impl Lexer {
             line: 1,
             col: 1,
             last_token: None,
+            diagnostics: Vec::new(),
         }
     }
 
-    pub fn tokenize(&mut self) -> Vec<Token> {
+    // Bad input is reported and replaced by the closest valid token, so the
+    // parser still sees the rest of the file.
+    pub fn tokenize(&mut self) -> (Vec<Token>, Vec<Diagnostic>) {
         let mut tokens = Vec::new();
         loop {
             let t = self.next_token();
@@ -29,12 +39,21 @@ impl Lexer {
                 break;
             }
         }
-        tokens
+        (tokens, std::mem::take(&mut self.diagnostics))
+    }
+
+    fn error(&mut self, code: &'static str, msg: &str, line: usize, col: usize, hint: &str) {
+        let d = Diagnostic::error(code, msg, Span { line, col });
+        self.diagnostics.push(if hint.is_empty() {
+            d
+        } else {
+            d.with_hint(hint)
+        });
     }
 
     fn next_token(&mut self) -> Token {
-        if let Some(k) = self.skip_ws_and_comments() {
-            return self.make(k);
+        if let Some(t) = self.skip_ws_and_comments() {
+            return t;
         }
 
         let c = match self.peek() {
@@ -42,7 +61,19 @@ impl Lexer {
             None => return self.make(TokenKind::Eof),
         };
 
+        // tokens are reported at the position they start at
+        let (line, col) = (self.line, self.col);
+
         let kind = match c {
+            '.' if self.peek2('.') => {
+                self.bump2();
+                if self.peek() == Some('=') {
+                    self.bump();
+                    TokenKind::DotDotEq
+                } else {
+                    TokenKind::DotDot
+                }
+            }
             '.' => {
                 self.bump();
                 TokenKind::Dot
@@ -63,30 +94,86 @@ impl Lexer {
                 self.bump();
                 TokenKind::RBrace
             }
+            '[' => {
+                self.bump();
+                TokenKind::LBracket
+            }
+            ']' => {
+                self.bump();
+                TokenKind::RBracket
+            }
             ',' => {
                 self.bump();
                 TokenKind::Comma
             }
+            ':' => {
+                self.bump();
+                TokenKind::Colon
+            }
+            '?' => {
+                self.bump();
+                TokenKind::Question
+            }
             ';' => {
                 self.bump();
                 TokenKind::Semicolon
             }
+            '+' if self.peek2('+') => {
+                self.bump2();
+                TokenKind::PlusPlus
+            }
+            '+' if self.peek2('=') => {
+                self.bump2();
+                TokenKind::PlusEq
+            }
             '+' => {
                 self.bump();
                 TokenKind::Plus
             }
+            '-' if self.peek2('-') => {
+                self.bump2();
+                TokenKind::MinusMinus
+            }
+            '-' if self.peek2('=') => {
+                self.bump2();
+                TokenKind::MinusEq
+            }
             '-' => {
                 self.bump();
                 TokenKind::Minus
             }
+            '*' if self.peek2('*') => {
+                self.bump2();
+                TokenKind::StarStar
+            }
+            '*' if self.peek2('=') => {
+                self.bump2();
+                TokenKind::StarEq
+            }
             '*' => {
                 self.bump();
                 TokenKind::Star
             }
+            '%' if self.peek2('=') => {
+                self.bump2();
+                TokenKind::PercentEq
+            }
+            '%' => {
+                self.bump();
+                TokenKind::Percent
+            }
+            '/' if self.peek2('=') => {
+                self.bump2();
+                TokenKind::SlashEq
+            }
             '/' => {
                 self.bump();
                 TokenKind::Slash
             }
+            '=' if self.peek2('>') => {
+                self.bump2();
+                TokenKind::FatArrow
+            }
             '=' => {
                 if self.peek2('=') {
                     self.bump2();
@@ -130,58 +217,86 @@ impl Lexer {
                 self.bump2();
                 TokenKind::OrOr
             }
-            '"' => self.read_string(),
+            '"' => self.read_string(false),
+            'r' if self.peek2('"') => self.read_string(true),
             c if c.is_ascii_digit() => self.read_number(),
             c if c.is_ascii_alphabetic() || c == '_' => self.read_ident(),
-            _ => {
+            c => {
+                let hint = match c {
+                    '&' => "did you mean `&&`?",
+                    '|' => "did you mean `||`?",
+                    '\'' | '`' => "strings use double quotes: \"text\"",
+                    '\u{201c}' | '\u{201d}' => "replace the curly quote with a plain `\"`",
+                    _ => "",
+                };
+                let shown = if c.is_control() {
+                    c.escape_debug().to_string()
+                } else {
+                    c.to_string()
+                };
                 self.bump();
-                return self.next_token();
+                self.error(
+                    "E010",
+                    &format!("unexpected character `{}`", shown),
+                    line,
+                    col,
+                    hint,
+                );
+                TokenKind::Error
             }
         };
 
         self.last_token = Some(kind.clone());
-        self.make(kind)
+        Token { kind, line, col }
     }
 
-    fn skip_ws_and_comments(&mut self) -> Option<TokenKind> {
-        let mut newline = false;
+    // an inserted semicolon is placed at the end of the line it terminates
+    fn skip_ws_and_comments(&mut self) -> Option<Token> {
+        let mut newline = None;
 
         loop {
             match self.peek() {
                 Some('\n') => {
+                    newline.get_or_insert((self.line, self.col));
                     self.pos += 1;
                     self.line += 1;
                     self.col = 1;
-                    newline = true;
                 }
                 Some(c) if c.is_whitespace() => {
                     self.bump();
                 }
                 Some('#') => {
                     while let Some(c) = self.peek() {
-                        self.pos += 1;
                         if c == '\n' {
-                            self.line += 1;
-                            self.col = 1;
-                            newline = true;
                             break;
                         }
+                        self.bump();
                     }
                 }
                 _ => break,
             }
         }
 
-        if newline {
+        if let Some((line, col)) = newline {
             if let Some(t) = &self.last_token {
                 match t {
                     TokenKind::Ident(_)
                     | TokenKind::Number(_)
+                    | TokenKind::Float(_)
                     | TokenKind::Str(_)
+                    | TokenKind::Template(_)
+                    | TokenKind::Error
+                    | TokenKind::PlusPlus
+                    | TokenKind::MinusMinus
                     | TokenKind::True
                     | TokenKind::False
-                    | TokenKind::RParen => {
-                        return Some(TokenKind::Semicolon);
+                    | TokenKind::RParen
+                    | TokenKind::RBracket => {
+                        return Some(Token {
+                            kind: TokenKind::Semicolon,
+                            line,
+                            col,
+                        });
                     }
                     _ => {}
                 }
@@ -217,20 +332,270 @@ impl Lexer {
         self.src.get(self.pos + 1) == Some(&c)
     }
 
-    fn read_string(&mut self) -> TokenKind {
-        self.bump();
+    // like bump, but keeps line and column right across newlines
+    fn next_char(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        if c == '\n' {
+            self.pos += 1;
+            self.line += 1;
+            self.col = 1;
+        } else {
+            self.bump();
+        }
+        Some(c)
+    }
+
+    fn at_triple_quote(&self) -> bool {
+        self.src[self.pos..].starts_with(&['"', '"', '"'])
+    }
+
+    // "text" ends at the end of its line, """text""" may span lines, and an r in
+    // front of either turns off escapes and {placeholders}
+    fn read_string(&mut self, raw: bool) -> TokenKind {
+        let (line, col) = (self.line, self.col);
+        if raw {
+            self.bump();
+        }
+        let triple = self.at_triple_quote();
+        if triple {
+            self.bump2();
+            self.bump();
+            // the text starts on the line after the opening quotes
+            if self.peek() == Some('\r') && self.peek2('\n') {
+                self.bump();
+            }
+            if self.peek() == Some('\n') {
+                self.next_char();
+            }
+        } else {
+            self.bump();
+        }
+
         let mut s = String::new();
+        let mut parts = Vec::new();
+        loop {
+            if triple && self.at_triple_quote() {
+                self.bump2();
+                self.bump();
+                break;
+            }
+            match self.peek() {
+                Some('"') if !triple => {
+                    self.bump();
+                    break;
+                }
+                Some('\n') if !triple => {
+                    self.error(
+                        "E009",
+                        "unterminated string",
+                        line,
+                        col,
+                        "add a closing `\"`, or use \"\"\" for text over several lines",
+                    );
+                    break;
+                }
+                None => {
+                    let hint = if triple {
+                        "add a closing \"\"\""
+                    } else {
+                        "add a closing `\"`"
+                    };
+                    self.error("E009", "unterminated string", line, col, hint);
+                    break;
+                }
+                Some('\r') if triple && self.peek2('\n') => self.bump(),
+                Some('\\') if !raw => self.read_escape(&mut s),
+                // doubled braces are literal, as in Rust's format strings
+                Some('{') if !raw && self.peek2('{') => {
+                    s.push('{');
+                    self.bump2();
+                }
+                Some('}') if !raw && self.peek2('}') => {
+                    s.push('}');
+                    self.bump2();
+                }
+                Some('{') if !raw => {
+                    parts.push(TemplatePart::Text(std::mem::take(&mut s)));
+                    let code = self.read_placeholder(triple);
+                    // Already reported. An unclosed placeholder has taken the
+                    // rest of the string as code; after a closed one the rest
+                    // is skipped, so its closing quote doesn't start another.
+                    if code.iter().any(|t| t.kind == TokenKind::Error) {
+                        if code.last().is_some_and(|t| t.kind == TokenKind::RBrace) {
+                            self.skip_string(triple);
+                        }
+                        return TokenKind::Error;
+                    }
+                    parts.push(TemplatePart::Code(code));
+                }
+                Some(c) => {
+                    s.push(c);
+                    self.next_char();
+                }
+            }
+        }
+
+        if parts.is_empty() {
+            return TokenKind::Str(s);
+        }
+        parts.push(TemplatePart::Text(s));
+        TokenKind::Template(parts)
+    }
+
+    // Lexes the code between the braces of a placeholder, up to and including
+    // the `}` that closes it. An unclosed placeholder becomes a single error
+    // token and lexing resumes where the code ran out.
+    fn read_placeholder(&mut self, triple: bool) -> Vec<Token> {
+        let (line, col) = (self.line, self.col);
+        self.bump();
+        let last_token = self.last_token.take();
+        let mut tokens = Vec::new();
+        let mut depth = 0;
+
+        loop {
+            let saved = (self.pos, self.line, self.col, self.diagnostics.len());
+            let t = self.next_token();
+            // a plain string cannot continue on the next line
+            let unclosed = t.kind == TokenKind::Eof || (!triple && t.line != line);
+            if unclosed {
+                (self.pos, self.line, self.col) = (saved.0, saved.1, saved.2);
+                self.diagnostics.truncate(saved.3);
+                self.error(
+                    "E014",
+                    "unclosed `{` in string",
+                    line,
+                    col,
+                    "close the placeholder with `}`, or write {{ for a literal brace",
+                );
+                tokens = vec![Token {
+                    kind: TokenKind::Error,
+                    line,
+                    col,
+                }];
+                break;
+            }
+
+            let done = match t.kind {
+                // newlines inside a placeholder are just whitespace
+                TokenKind::Semicolon => continue,
+                TokenKind::LBrace => {
+                    depth += 1;
+                    false
+                }
+                TokenKind::RBrace if depth == 0 => true,
+                TokenKind::RBrace => {
+                    depth -= 1;
+                    false
+                }
+                _ => false,
+            };
+            tokens.push(t);
+            if done {
+                break;
+            }
+        }
+
+        self.last_token = last_token;
+        tokens
+    }
+
+    // moves past the closing quotes, or to the end of the line when a plain
+    // string has none
+    fn skip_string(&mut self, triple: bool) {
+        loop {
+            if triple && self.at_triple_quote() {
+                self.bump2();
+                self.bump();
+                return;
+            }
+            match self.peek() {
+                None => return,
+                Some('\n') if !triple => return,
+                Some('"') if !triple => {
+                    self.bump();
+                    return;
+                }
+                // an escaped quote doesn't end the string
+                Some('\\') if self.src.get(self.pos + 1).is_some_and(|&c| c != '\n') => {
+                    self.bump2();
+                }
+                Some(_) => {
+                    self.next_char();
+                }
+            }
+        }
+    }
+
+    fn read_escape(&mut self, s: &mut String) {
+        let (line, col) = (self.line, self.col);
+        self.bump();
+        let c = match self.peek() {
+            Some(c) if c != '\n' && c != '\r' => c,
+            _ => {
+                self.error("E012", "unfinished escape", line, col, ESCAPES);
+                return;
+            }
+        };
+        self.bump();
+        match c {
+            'n' => s.push('\n'),
+            't' => s.push('\t'),
+            'r' => s.push('\r'),
+            '0' => s.push('\0'),
+            '"' => s.push('"'),
+            '{' => s.push('{'),
+            '}' => s.push('}'),
+            '\\' => s.push('\\'),
+            'u' => match self.read_unicode() {
+                Some(c) => s.push(c),
+                None => self.error(
+                    "E013",
+                    "invalid unicode escape",
+                    line,
+                    col,
+                    "write it as \\u{1F600}, with 1 to 6 hex digits",
+                ),
+            },
+            c => {
+                self.error(
+                    "E012",
+                    &format!("unknown escape `\\{}`", c),
+                    line,
+                    col,
+                    ESCAPES,
+                );
+                s.push('\\');
+                s.push(c);
+            }
+        }
+    }
+
+    // the {XXXX} part of \u{XXXX}, consumed even when it is invalid
+    fn read_unicode(&mut self) -> Option<char> {
+        if self.peek() != Some('{') {
+            return None;
+        }
+        self.bump();
+        let mut hex = String::new();
         while let Some(c) = self.peek() {
-            self.bump();
-            if c == '"' {
+            if !c.is_ascii_hexdigit() {
                 break;
             }
-            s.push(c);
+            hex.push(c);
+            self.bump();
         }
-        TokenKind::Str(s)
+        if self.peek() != Some('}') {
+            return None;
+        }
+        self.bump();
+        if hex.is_empty() || hex.len() > 6 {
+            return None;
+        }
+        char::from_u32(u32::from_str_radix(&hex, 16).ok()?)
     }
 
     fn read_number(&mut self) -> TokenKind {
+        let (line, col) = (self.line, self.col);
         let mut s = String::new();
         while let Some(c) = self.peek() {
             if !c.is_ascii_digit() {
@@ -239,7 +604,44 @@ impl Lexer {
             s.push(c);
             self.bump();
         }
-        TokenKind::Number(s.parse().unwrap())
+
+        let fraction = self
+            .src
+            .get(self.pos + 1)
+            .is_some_and(|c| c.is_ascii_digit());
+        if self.peek() == Some('.') && fraction {
+            s.push('.');
+            self.bump();
+            while let Some(c) = self.peek() {
+                if !c.is_ascii_digit() {
+                    break;
+                }
+                s.push(c);
+                self.bump();
+            }
+            // digits with a single dot always parse, huge values become infinity
+            return TokenKind::Float(s.parse().unwrap_or(f64::INFINITY));
+        }
+
+        match s.parse() {
+            Ok(n) => TokenKind::Number(n),
+            // the digits of -9223372036854775808 don't fit on their own, so
+            // they stand for i64::MIN and the parser takes the minus as part
+            // of the literal
+            Err(_) if s == "9223372036854775808" && self.last_token == Some(TokenKind::Minus) => {
+                TokenKind::Number(i64::MIN)
+            }
+            Err(_) => {
+                self.error(
+                    "E011",
+                    "number is too large",
+                    line,
+                    col,
+                    &format!("integers go up to {}, add `.0` for a float", i64::MAX),
+                );
+                TokenKind::Number(0)
+            }
+        }
     }
 
     fn read_ident(&mut self) -> TokenKind {
@@ -253,15 +655,27 @@ impl Lexer {
         }
         match s.as_str() {
             "let" => TokenKind::Var,
+            "const" => TokenKind::Const,
+            "import" => TokenKind::Import,
+            "struct" => TokenKind::Struct,
             "def" => TokenKind::Def,
+            "fn" => TokenKind::Fn,
             "return" => TokenKind::Return,
             "if" => TokenKind::If,
             "elif" => TokenKind::Elif,
             "else" => TokenKind::Else,
             "loop" => TokenKind::Loop,
             "while" => TokenKind::While,
+            "for" => TokenKind::For,
+            "in" => TokenKind::In,
             "async" => TokenKind::Async,
             "await" => TokenKind::Await,
+            "try" => TokenKind::Try,
+            "catch" => TokenKind::Catch,
+            "match" => TokenKind::Match,
+            "select" => TokenKind::Select,
+            "lock" => TokenKind::Lock,
+            "finally" => TokenKind::Finally,
             "break" => TokenKind::Break,
             "continue" => TokenKind::Continue,
             "true" => TokenKind::True,
@@ -270,3 +684,160 @@ impl Lexer {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn codes(src: &str) -> Vec<&'static str> {
+        let (_, diagnostics) = Lexer::new(src).tokenize();
+        diagnostics.iter().map(|d| d.code).collect()
+    }
+
+    #[test]
+    fn a_bad_placeholder_is_reported_once() {
+        assert_eq!(codes("print(\"{@} b\")"), ["E010"]);
+        assert_eq!(codes("print(\"{a @} b \\\" {c}\")\nprint(\"x\")"), ["E010"]);
+        assert_eq!(
+            codes("print(\"\"\"\n{ @ }\n\"q\"\n\"\"\")\nprint(\"x\")"),
+            ["E010"]
+        );
+    }
+
+    #[test]
+    fn an_unclosed_placeholder_leaves_the_next_line_alone() {
+        assert_eq!(codes("print(\"{a b\nprint(\"x\")"), ["E014"]);
+    }
+
+    fn kinds(src: &str) -> Vec<TokenKind> {
+        let (tokens, diagnostics) = Lexer::new(src).tokenize();
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        tokens.into_iter().map(|t| t.kind).collect()
+    }
+
+    #[test]
+    fn an_unterminated_string_is_reported_where_it_starts() {
+        let (_, diagnostics) = Lexer::new("let a = 1\nprint(\"abc").tokenize();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].code, "E009");
+        assert_eq!((diagnostics[0].span.line, diagnostics[0].span.col), (2, 7));
+        // a plain string stops at the end of its line
+        assert_eq!(codes("print(\"abc\nprint(\"x\")"), ["E009"]);
+        assert_eq!(codes("print(\"\"\"\nabc\n"), ["E009"]);
+    }
+
+    #[test]
+    fn an_unknown_character_is_an_error_token() {
+        let (tokens, diagnostics) = Lexer::new("a @ b").tokenize();
+        assert_eq!(tokens[1].kind, TokenKind::Error);
+        assert_eq!(diagnostics[0].code, "E010");
+        assert_eq!(diagnostics[0].message, "unexpected character `@`");
+        assert_eq!(diagnostics[0].span.col, 3);
+        assert_eq!(diagnostics[0].hint, None);
+        let (_, diagnostics) = Lexer::new("a & b").tokenize();
+        assert_eq!(diagnostics[0].hint.as_deref(), Some("did you mean `&&`?"));
+    }
+
+    #[test]
+    fn a_number_past_the_integer_range_is_reported() {
+        assert_eq!(codes("9223372036854775807"), Vec::<&str>::new());
+        assert_eq!(codes("9223372036854775808"), ["E011"]);
+        assert_eq!(codes("99999999999999999999999"), ["E011"]);
+        assert_eq!(codes("-99999999999999999999999"), ["E011"]);
+        // a float never overflows
+        assert_eq!(
+            kinds("99999999999999999999999.5")[0],
+            TokenKind::Float(1e23)
+        );
+    }
+
+    #[test]
+    fn the_smallest_integer_can_be_written() {
+        assert_eq!(
+            kinds("-9223372036854775808"),
+            [
+                TokenKind::Minus,
+                TokenKind::Number(i64::MIN),
+                TokenKind::Eof
+            ]
+        );
+    }
+
+    fn string(src: &str) -> String {
+        match kinds(src).remove(0) {
+            TokenKind::Str(s) => s,
+            t => panic!("expected a string, got {:?}", t),
+        }
+    }
+
+    #[test]
+    fn escapes_become_the_characters_they_name() {
+        assert_eq!(
+            string(r#""a\nb\tc\rd\0e\"f\\g\{h\}""#),
+            "a\nb\tc\rd\0e\"f\\g{h}"
+        );
+        assert_eq!(string(r#""\u{41}\u{1F600}""#), "A\u{1F600}");
+    }
+
+    #[test]
+    fn bad_escapes_are_reported_where_they_start() {
+        let (_, diagnostics) = Lexer::new(r#"print("ab\q")"#).tokenize();
+        assert_eq!(diagnostics[0].code, "E012");
+        assert_eq!(diagnostics[0].message, "unknown escape `\\q`");
+        assert_eq!(diagnostics[0].span.col, 10);
+        assert_eq!(codes("print(\"ab\\\nprint(1)"), ["E012", "E009"]);
+        assert_eq!(codes(r#""\u{110000}""#), ["E013"]);
+        assert_eq!(codes(r#""\u{}""#), ["E013"]);
+        assert_eq!(codes(r#""\u41""#), ["E013"]);
+        assert_eq!(codes(r#""\u{1234567}""#), ["E013"]);
+    }
+
+    #[test]
+    fn raw_strings_keep_backslashes_and_braces() {
+        assert_eq!(string(r#"r"C:\new\{x}""#), r"C:\new\{x}");
+        assert_eq!(string(r#"r"""a\n"b"c""""#), r#"a\n"b"c"#);
+    }
+
+    #[test]
+    fn triple_quoted_strings_span_lines() {
+        assert_eq!(
+            string("\"\"\"\nfirst\n\"quoted\"\nlast\"\"\""),
+            "first\n\"quoted\"\nlast"
+        );
+        assert_eq!(string("\"\"\"\r\nfirst\r\nlast\"\"\""), "first\nlast");
+        assert_eq!(string("\"\"\"one\"\"\""), "one");
+    }
+
+    #[test]
+    fn doubled_braces_are_literal() {
+        assert_eq!(string(r#""{{a}} }""#), "{a} }");
+        assert_eq!(string(r#"r"{{a}}""#), "{{a}}");
+    }
+
+    #[test]
+    fn a_placeholder_keeps_its_tokens_and_positions() {
+        let parts = match kinds(r#""x={a + 1} {{y}}""#).remove(0) {
+            TokenKind::Template(parts) => parts,
+            t => panic!("expected a template, got {:?}", t),
+        };
+        let [
+            TemplatePart::Text(before),
+            TemplatePart::Code(code),
+            TemplatePart::Text(after),
+        ] = parts.as_slice()
+        else {
+            panic!("{:?}", parts);
+        };
+        assert_eq!((before.as_str(), after.as_str()), ("x=", " {y}"));
+        let code: Vec<_> = code.iter().map(|t| (t.kind.clone(), t.col)).collect();
+        assert_eq!(
+            code,
+            [
+                (TokenKind::Ident("a".into()), 5),
+                (TokenKind::Plus, 7),
+                (TokenKind::Number(1), 9),
+                (TokenKind::RBrace, 10),
+            ]
+        );
+    }
+}
@@ -0,0 +1,79 @@
+use crate::task::{self, Waker};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+// The locks behind `lock (name) { ... }`, made the first time a name is
+// used. The task holding a lock can enter it again, so a function that
+// takes a lock can call another one taking the same lock.
+#[derive(Default)]
+pub struct Locks {
+    locks: Mutex<HashMap<String, Arc<Lock>>>,
+}
+
+#[derive(Default)]
+struct Lock {
+    state: Mutex<Holder>,
+}
+
+#[derive(Default)]
+struct Holder {
+    // the holding task (0 for the main script) and how many times it entered
+    holder: Option<(usize, usize)>,
+    waiting: Vec<Waker>,
+}
+
+// Releases the lock when the block is left, whether it ends, breaks, returns
+// or unwinds with an error or a cancel.
+pub struct Guard(Arc<Lock>);
+
+impl Locks {
+    // waits until the lock is free; None when the task is cancelled meanwhile
+    pub fn acquire(&self, name: &str) -> Option<Guard> {
+        let lock = self
+            .locks
+            .lock()
+            .unwrap()
+            .entry(name.to_string())
+            .or_default()
+            .clone();
+
+        let me = task::current().map_or(0, |t| Arc::as_ptr(&t) as usize);
+        loop {
+            {
+                let mut state = lock.state.lock().unwrap();
+                match &mut state.holder {
+                    None => state.holder = Some((me, 1)),
+                    Some((id, depth)) if *id == me => *depth += 1,
+                    Some(_) => {
+                        if task::cancelled() {
+                            return None;
+                        }
+                        state.waiting.push(task::waker());
+                        drop(state);
+                        task::wait(None);
+                        continue;
+                    }
+                }
+            }
+            return Some(Guard(lock));
+        }
+    }
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        let mut state = self.0.state.lock().unwrap();
+        if let Some((_, depth)) = &mut state.holder {
+            *depth -= 1;
+            if *depth == 0 {
+                state.holder = None;
+                // all of them, one that was cancelled meanwhile won't take it
+                let waiting = std::mem::take(&mut state.waiting);
+                drop(state);
+                for w in waiting {
+                    w.wake();
+                }
+            }
+        }
+    }
+}
@@ -1,15 +1,24 @@
 mod ast;
+mod channel;
+mod diagnostic;
 mod engine;
 mod functions;
+mod input;
 mod interpreter;
+mod kill_switch;
 mod lexer;
+mod lock;
+mod module;
 mod parser;
+mod scheduler;
+mod scope;
+mod task;
 mod token;
 
-use crate::{engine::Engine, lexer::Lexer, parser::Parser};
+use crate::{engine::Engine, module::Loader};
 use std::env;
-use std::fs;
 use std::io::{self, Read};
+use std::sync::Arc;
 
 fn wait_for_keypress() {
     println!("\nPress any key to exit...");
@@ -24,30 +33,23 @@ fn main() {
         }
     };
 
-    let src = match fs::read_to_string(&file) {
-        Ok(s) => s,
-        Err(e) => {
-            println!("Failed to read {}: {}", file, e);
-            wait_for_keypress();
-            return;
-        }
-    };
-
-    let mut lexer = Lexer::new(&src);
-    let tokens = lexer.tokenize();
-    let mut parser = Parser::new(tokens);
+    // the main file and everything it imports is checked before anything runs
+    let mut loader = Loader::new();
+    if let Err(e) = loader.load(&file) {
+        println!("Failed to read {}: {}", file, e);
+        wait_for_keypress();
+        return;
+    }
+    print!("{}", loader.report());
+    if loader.has_errors() {
+        wait_for_keypress();
+        return;
+    }
 
-    match parser.parse() {
-        Ok(ast) => {
-            let engine = Engine::new();
-            if let Err(e) = engine.run(ast) {
-                println!("Runtime error: {}", e);
-                wait_for_keypress();
-            }
-        }
-        Err(e) => {
-            println!("Syntax error: {}", e);
-            wait_for_keypress();
-        }
+    let modules = Arc::new(loader.into_modules());
+    let engine = Engine::new(modules.clone());
+    if let Err(e) = engine.run() {
+        print!("{}", e.render(&modules));
+        wait_for_keypress();
     }
 }
@@ -0,0 +1,267 @@
+use crate::{
+    ast::{Node, NodeKind, Span},
+    diagnostic::{self, Diagnostic, Severity},
+    lexer::Lexer,
+    parser::Parser,
+};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// A parsed source file. Index 0 is the file the script was started with.
+pub struct Module {
+    pub file: String,
+    pub src: String,
+    pub ast: Vec<Node>,
+    // import path as written in the file -> index of the imported module
+    pub imports: HashMap<String, usize>,
+}
+
+// Reads the main file and everything it imports before anything runs.
+// Every file is read and parsed once, however many files import it.
+pub struct Loader {
+    modules: Vec<Module>,
+    diagnostics: Vec<Vec<Diagnostic>>,
+    ids: HashMap<PathBuf, usize>,
+    // modules whose imports are being loaded, outermost first
+    loading: Vec<usize>,
+    search: Vec<PathBuf>,
+}
+
+impl Loader {
+    // imports are looked up next to the importing file, then in each
+    // directory listed in NUM_PATH
+    pub fn new() -> Self {
+        let search = match env::var_os("NUM_PATH") {
+            Some(paths) => env::split_paths(&paths).collect(),
+            None => Vec::new(),
+        };
+        Self {
+            modules: Vec::new(),
+            diagnostics: Vec::new(),
+            ids: HashMap::new(),
+            loading: Vec::new(),
+            search,
+        }
+    }
+
+    pub fn load(&mut self, file: &str) -> Result<(), String> {
+        self.load_file(Path::new(file)).map(|_| ())
+    }
+
+    fn load_file(&mut self, path: &Path) -> Result<usize, String> {
+        let key = fs::canonicalize(path).map_err(|e| e.to_string())?;
+        if let Some(&id) = self.ids.get(&key) {
+            return Ok(id);
+        }
+        let src = fs::read_to_string(path).map_err(|e| e.to_string())?;
+
+        let (tokens, mut diagnostics) = Lexer::new(&src).tokenize();
+        let (ast, parse_diagnostics) = Parser::new(tokens).parse();
+        diagnostics.extend(parse_diagnostics);
+
+        let imports: Vec<(String, Span)> = ast
+            .iter()
+            .filter_map(|n| match &n.kind {
+                NodeKind::Import { path, .. } => Some((path.clone(), n.span)),
+                _ => None,
+            })
+            .collect();
+
+        let id = self.modules.len();
+        self.modules.push(Module {
+            file: path.display().to_string(),
+            src,
+            ast,
+            imports: HashMap::new(),
+        });
+        self.diagnostics.push(diagnostics);
+        self.ids.insert(key, id);
+
+        self.loading.push(id);
+        let dir = path.parent().unwrap_or(Path::new(""));
+        for (import, span) in imports {
+            match self.import(dir, &import, span) {
+                Ok(child) => {
+                    self.modules[id].imports.insert(import, child);
+                }
+                Err(d) => self.diagnostics[id].push(d),
+            }
+        }
+        self.loading.pop();
+        Ok(id)
+    }
+
+    fn import(&mut self, dir: &Path, import: &str, span: Span) -> Result<usize, Diagnostic> {
+        let path = std::iter::once(dir.to_path_buf())
+            .chain(self.search.iter().cloned())
+            .map(|d| d.join(import))
+            .find(|p| p.is_file());
+        let Some(path) = path else {
+            let d = Diagnostic::error("E019", format!("cannot find module \"{}\"", import), span);
+            let hint = match dir.display().to_string() {
+                d if d.is_empty() => {
+                    "paths are relative to the importing file, or to a directory in NUM_PATH"
+                        .to_string()
+                }
+                d => format!("looked in {} and the directories in NUM_PATH", d),
+            };
+            return Err(d.with_hint(hint));
+        };
+
+        // a module that is still loading its own imports can't be imported again
+        let key = fs::canonicalize(&path).ok();
+        let cycle = key.and_then(|k| self.ids.get(&k).copied());
+        if let Some(start) = cycle.and_then(|c| self.loading.iter().position(|&m| m == c)) {
+            let mut chain: Vec<&str> = self.loading[start..]
+                .iter()
+                .map(|&m| self.modules[m].file.as_str())
+                .collect();
+            chain.push(&self.modules[self.loading[start]].file);
+            let msg = format!("import cycle: {}", chain.join(" -> "));
+            return Err(Diagnostic::error("E020", msg, span)
+                .with_hint("move what both files need into a third file that imports neither"));
+        }
+
+        self.load_file(&path).map_err(|e| {
+            let msg = format!("cannot read module \"{}\": {}", import, e);
+            Diagnostic::error("E019", msg, span)
+        })
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics
+            .iter()
+            .flatten()
+            .any(|d| d.severity == Severity::Error)
+    }
+
+    // the diagnostics of every file, file by file, with one summary at the end
+    pub fn report(&self) -> String {
+        let mut out = String::new();
+        for (m, diagnostics) in self.modules.iter().zip(self.diagnostics.iter()) {
+            out.push_str(&diagnostic::render_file(diagnostics, &m.file, &m.src));
+        }
+        let all: Vec<Diagnostic> = self.diagnostics.iter().flatten().cloned().collect();
+        out.push_str(&diagnostic::summary(&all));
+        out
+    }
+
+    pub fn into_modules(self) -> Vec<Module> {
+        self.modules
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // an empty directory of its own for each test
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("num-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write(dir: &Path, file: &str, src: &str) -> String {
+        let path = dir.join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, src).unwrap();
+        path.display().to_string()
+    }
+
+    fn codes(loader: &Loader) -> Vec<(&'static str, String)> {
+        loader
+            .diagnostics
+            .iter()
+            .flatten()
+            .map(|d| (d.code, d.message.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn a_file_imported_twice_is_loaded_once() {
+        let dir = temp_dir("once");
+        write(&dir, "lib/util.num", "def one() { return 1 }");
+        write(&dir, "lib/a.num", "import \"util.num\" as u");
+        let main = write(
+            &dir,
+            "main.num",
+            "import \"lib/a.num\" as a\nimport \"lib/util.num\" as u",
+        );
+
+        let mut loader = Loader::new();
+        loader.load(&main).unwrap();
+        assert!(codes(&loader).is_empty(), "{:?}", codes(&loader));
+        assert_eq!(loader.modules.len(), 3);
+        assert_eq!(loader.modules[0].imports["lib/util.num"], 2);
+        assert_eq!(loader.modules[1].imports["util.num"], 2);
+    }
+
+    #[test]
+    fn files_that_import_each_other_are_a_cycle() {
+        let dir = temp_dir("cycle");
+        write(&dir, "b.num", "import \"c.num\" as c");
+        write(&dir, "c.num", "import \"b.num\" as b");
+        let main = write(&dir, "main.num", "import \"b.num\" as b");
+
+        let mut loader = Loader::new();
+        loader.load(&main).unwrap();
+        let b = dir.join("b.num").display().to_string();
+        let c = dir.join("c.num").display().to_string();
+        assert_eq!(
+            codes(&loader),
+            [("E020", format!("import cycle: {} -> {} -> {}", b, c, b))]
+        );
+        assert!(loader.has_errors());
+    }
+
+    #[test]
+    fn imports_fall_back_to_the_search_path() {
+        let dir = temp_dir("search");
+        let lib = dir.join("shared");
+        write(&lib, "mouse.num", "def move() { }");
+        let main = write(
+            &dir,
+            "scripts/main.num",
+            "import \"mouse.num\" as m\nimport \"missing.num\" as x",
+        );
+
+        // what NUM_PATH=<dir>/shared gives
+        let mut loader = Loader::new();
+        loader.search = vec![lib];
+        loader.load(&main).unwrap();
+        assert_eq!(loader.modules.len(), 2);
+        assert_eq!(
+            codes(&loader),
+            [("E019", "cannot find module \"missing.num\"".to_string())]
+        );
+    }
+
+    #[test]
+    fn imported_names_are_reached_through_the_alias() {
+        let dir = temp_dir("alias");
+        write(
+            &dir,
+            "helpers.num",
+            "let speed = 2\ndef scale(x) { return x * speed }",
+        );
+        let main = write(
+            &dir,
+            "main.num",
+            "import \"helpers.num\" as h\nh.speed = 3\nthrow([h.scale(2), h.speed])",
+        );
+
+        let mut loader = Loader::new();
+        loader.load(&main).unwrap();
+        let modules = std::sync::Arc::new(loader.into_modules());
+        let mut builtins = HashMap::new();
+        crate::functions::register_all(&mut builtins);
+        let ctx = crate::interpreter::Context::new(builtins, modules.clone());
+        // the script throws what it found, as nothing else gets out of it
+        let e = ctx.run(&modules[0].ast).unwrap_err();
+        assert_eq!(e.message(), "[6, 3]");
+    }
+}
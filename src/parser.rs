@@ -1,65 +1,271 @@
+use std::collections::HashMap;
+
 use crate::{
-    ast::{Expr, Node, Op},
-    token::{Token, TokenKind},
+    ast::{AssignTarget, Expr, ExprKind, Node, NodeKind, Op, Pattern, SelectArm, Span},
+    diagnostic::{Diagnostic, keyword_hint},
+    token::{TemplatePart, Token, TokenKind},
 };
 
 pub struct Parser {
     tokens: Vec<Token>,
     pos: usize,
+    diagnostics: Vec<Diagnostic>,
+    // set by `use truthy`, conditions accept any value from then on
+    truthy: bool,
+    // names declared in each enclosing scope, to catch misuse of constants early
+    scopes: Vec<HashMap<String, Binding>>,
+}
+
+#[derive(Clone, Copy)]
+struct Binding {
+    constant: bool,
+    line: usize,
 }
 
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Self {
-        Self { tokens, pos: 0 }
+        Self {
+            tokens,
+            pos: 0,
+            diagnostics: Vec::new(),
+            truthy: false,
+            scopes: vec![HashMap::new()],
+        }
     }
 
-    pub fn parse(&mut self) -> Result<Vec<Node>, String> {
+    // Parses the whole file. A broken statement is reported and skipped so
+    // every syntax error in the file shows up in one run.
+    pub fn parse(&mut self) -> (Vec<Node>, Vec<Diagnostic>) {
         let mut nodes = Vec::new();
-        while !self.check(TokenKind::Eof) {
-            nodes.push(self.stmt()?);
+        loop {
+            while self.match_tok(TokenKind::Semicolon) {}
+            if self.check(TokenKind::Eof) {
+                break;
+            }
+            if let Some(n) = self.recover_stmt() {
+                nodes.push(n);
+            }
         }
-        Ok(nodes)
+        (nodes, std::mem::take(&mut self.diagnostics))
     }
 
-    fn err<T>(&self, msg: &str) -> Result<T, String> {
-        let t = self.peek();
-        Err(format!("line {}, column {}: {}", t.line, t.col, msg))
+    fn recover_stmt(&mut self) -> Option<Node> {
+        let start = self.pos;
+        let depth = self.scopes.len();
+        match self.stmt() {
+            Ok(n) => Some(n),
+            Err(d) => {
+                self.scopes.truncate(depth);
+                // a bad character already has its own error from the lexer
+                let end = (self.pos + 1).min(self.tokens.len());
+                if !self.tokens[start..end]
+                    .iter()
+                    .any(|t| t.kind == TokenKind::Error)
+                {
+                    self.diagnostics.push(d);
+                }
+                self.synchronize(start);
+                // a token no statement can start with, like a stray `}`
+                if self.pos == start && !self.check(TokenKind::Eof) {
+                    self.advance();
+                }
+                None
+            }
+        }
+    }
+
+    // Skips to the start of the next statement: past a `;` or newline, or up to
+    // a statement keyword or the `}` closing the enclosing block. Braces opened
+    // by the broken statement are skipped as a whole so their `}` does not end
+    // the enclosing block early.
+    fn synchronize(&mut self, start: usize) {
+        let mut depth = self.tokens[start..self.pos.min(self.tokens.len())]
+            .iter()
+            .fold(0usize, |d, t| match t.kind {
+                TokenKind::LBrace => d + 1,
+                TokenKind::RBrace => d.saturating_sub(1),
+                _ => d,
+            });
+
+        loop {
+            match self.peek().kind {
+                TokenKind::Eof => return,
+                TokenKind::LBrace => depth += 1,
+                TokenKind::RBrace if depth > 0 => {
+                    depth -= 1;
+                    self.advance();
+                    if depth == 0 {
+                        return;
+                    }
+                    continue;
+                }
+                TokenKind::RBrace => return,
+                _ if depth > 0 => {}
+                TokenKind::Semicolon => {
+                    self.advance();
+                    return;
+                }
+                TokenKind::Var
+                | TokenKind::Const
+                | TokenKind::Import
+                | TokenKind::Struct
+                | TokenKind::Def
+                | TokenKind::If
+                | TokenKind::Loop
+                | TokenKind::While
+                | TokenKind::For
+                | TokenKind::Async
+                | TokenKind::Await
+                | TokenKind::Return
+                | TokenKind::Try
+                | TokenKind::Match
+                | TokenKind::Select
+                | TokenKind::Lock
+                | TokenKind::Break
+                | TokenKind::Continue => return,
+                _ => {}
+            }
+            self.advance();
+        }
+    }
+
+    fn err<T>(&self, code: &'static str, msg: &str) -> Result<T, Diagnostic> {
+        Err(Diagnostic::error(code, msg, self.span()))
+    }
+
+    // "expected `)`, found `{`" at the current token
+    fn unexpected<T>(&self, code: &'static str, expected: &str) -> Result<T, Diagnostic> {
+        let found = self.peek().kind.describe();
+        self.err(code, &format!("expected {}, found {}", expected, found))
     }
 
-    fn stmt(&mut self) -> Result<Node, String> {
+    fn stmt(&mut self) -> Result<Node, Diagnostic> {
         while self.match_tok(TokenKind::Semicolon) {}
+        let span = self.span();
+        let kind = self.stmt_kind()?;
+        Ok(Node { kind, span })
+    }
+
+    fn stmt_kind(&mut self) -> Result<NodeKind, Diagnostic> {
         match &self.peek().kind {
-            TokenKind::Var => self.var_decl(),
+            TokenKind::Var | TokenKind::Const => self.var_decl(),
             TokenKind::If => self.if_stmt(),
             TokenKind::Loop => self.loop_stmt(),
             TokenKind::While => self.while_stmt(),
+            TokenKind::For => self.for_stmt(),
             TokenKind::Async => self.async_stmt(),
             TokenKind::Await => self.await_stmt(),
             TokenKind::Def => self.func_def(),
+            TokenKind::Struct => self.struct_def(),
             TokenKind::Return => self.return_stmt(),
+            TokenKind::Try => self.try_stmt(),
+            TokenKind::Match => self.match_stmt(),
+            TokenKind::Select => self.select_stmt(),
+            TokenKind::Lock => self.lock_stmt(),
+            TokenKind::Import => self.import_stmt(),
             TokenKind::Break => {
                 self.advance();
                 self.match_tok(TokenKind::Semicolon);
-                Ok(Node::Break)
+                Ok(NodeKind::Break)
             }
             TokenKind::Continue => {
                 self.advance();
                 self.match_tok(TokenKind::Semicolon);
-                Ok(Node::Continue)
+                Ok(NodeKind::Continue)
+            }
+            TokenKind::Ident(s)
+                if s == "use"
+                    && matches!(
+                        self.tokens.get(self.pos + 1).map(|t| &t.kind),
+                        Some(TokenKind::Ident(_))
+                    ) =>
+            {
+                self.use_stmt()
             }
             TokenKind::Ident(_) => self.call_or_assign(),
-            _ => self.err("Unexpected token"),
+            TokenKind::Elif | TokenKind::Else => {
+                let found = self.peek().kind.describe();
+                let d = Diagnostic::error("E001", format!("unexpected {}", found), self.span());
+                Err(d.with_hint(format!("{} must follow the `}}` of an `if` block", found)))
+            }
+            TokenKind::Catch | TokenKind::Finally => {
+                let found = self.peek().kind.describe();
+                let d = Diagnostic::error("E001", format!("unexpected {}", found), self.span());
+                Err(d.with_hint(format!("{} must follow the `}}` of a `try` block", found)))
+            }
+            k => self.err("E001", &format!("unexpected {}", k.describe())),
+        }
+    }
+
+    // `use` is only a keyword in front of a setting name, so it still works as a variable
+    fn use_stmt(&mut self) -> Result<NodeKind, Diagnostic> {
+        self.advance();
+        let span = self.span();
+        let name = self.ident()?;
+        match name.as_str() {
+            "truthy" => self.truthy = true,
+            _ => {
+                let d = Diagnostic::error("E015", format!("unknown setting `{}`", name), span);
+                return Err(d.with_hint("the only setting is `use truthy`"));
+            }
         }
+        self.terminator()?;
+        Ok(NodeKind::Use)
     }
 
-    fn async_stmt(&mut self) -> Result<Node, String> {
+    // import "path/to/helpers.num" as h
+    fn import_stmt(&mut self) -> Result<NodeKind, Diagnostic> {
+        let span = self.span();
+        self.advance();
+        if self.scopes.len() > 1 {
+            let d = Diagnostic::error("E018", "`import` is only allowed at the top level", span);
+            return Err(d.with_hint("imported files are loaded before the script starts running"));
+        }
+        let path = match &self.peek().kind {
+            TokenKind::Str(s) => s.clone(),
+            _ => return self.unexpected("E002", "a file path in quotes"),
+        };
+        self.advance();
+        if !matches!(&self.peek().kind, TokenKind::Ident(s) if s == "as") {
+            let found = self.peek().kind.describe();
+            let msg = format!("expected `as`, found {}", found);
+            let d = Diagnostic::error("E002", msg, self.span());
+            return Err(d.with_hint(format!(
+                "name the module, like `import \"{}\" as lib`",
+                path
+            )));
+        }
+        self.advance();
+        let at = self.span();
+        let alias = self.ident()?;
+        self.terminator()?;
+        self.declare(&alias, at, false);
+        Ok(NodeKind::Import { path, alias })
+    }
+
+    fn async_stmt(&mut self) -> Result<NodeKind, Diagnostic> {
         self.advance();
         while self.match_tok(TokenKind::Semicolon) {}
         let body = self.block()?;
-        Ok(Node::Async { body })
+        Ok(NodeKind::Async { body })
     }
 
-    fn await_stmt(&mut self) -> Result<Node, String> {
+    // lock (name) { ... }, the name is a label and not a variable
+    fn lock_stmt(&mut self) -> Result<NodeKind, Diagnostic> {
+        self.advance();
+        self.expect(TokenKind::LParen)?;
+        let name = match &self.peek().kind {
+            TokenKind::Ident(s) => s.clone(),
+            _ => return self.unexpected("E003", "a lock name"),
+        };
+        self.advance();
+        self.expect(TokenKind::RParen)?;
+        while self.match_tok(TokenKind::Semicolon) {}
+        let body = self.block()?;
+        Ok(NodeKind::Lock { name, body })
+    }
+
+    fn await_stmt(&mut self) -> Result<NodeKind, Diagnostic> {
         self.advance(); // await
         self.expect(TokenKind::LParen)?;
 
@@ -71,18 +277,60 @@ impl Parser {
         while self.match_tok(TokenKind::Semicolon) {}
 
         let body = self.block()?;
-        Ok(Node::Await { key, negated, body })
+        Ok(NodeKind::Await { key, negated, body })
+    }
+
+    // struct Point { x, y }, the fields may also go on lines of their own
+    fn struct_def(&mut self) -> Result<NodeKind, Diagnostic> {
+        self.advance();
+        let span = self.span();
+        let name = self.ident()?;
+        self.expect(TokenKind::LBrace)?;
+        let mut fields: Vec<String> = Vec::new();
+        loop {
+            while self.match_tok(TokenKind::Semicolon) {}
+            if self.match_tok(TokenKind::RBrace) {
+                break;
+            }
+            let at = self.span();
+            let field = self.ident()?;
+            if fields.contains(&field) {
+                let msg = format!("field `{}` is declared twice", field);
+                self.diagnostics.push(Diagnostic::error("E021", msg, at));
+            } else {
+                fields.push(field);
+            }
+            let newline = self.match_tok(TokenKind::Semicolon);
+            if !self.match_tok(TokenKind::Comma) && !newline {
+                self.expect(TokenKind::RBrace)?;
+                break;
+            }
+        }
+        self.declare(&name, span, false);
+        Ok(NodeKind::Struct { name, fields })
     }
 
-    fn func_def(&mut self) -> Result<Node, String> {
+    fn func_def(&mut self) -> Result<NodeKind, Diagnostic> {
         self.advance();
+        let span = self.span();
         let name = self.ident()?;
+        self.declare(&name, span, false);
+        self.scopes.push(HashMap::new());
+        let params = self.params()?;
+        let body = self.block()?;
+        self.scopes.pop();
+        Ok(NodeKind::Function { name, params, body })
+    }
+
+    fn params(&mut self) -> Result<Vec<(String, Option<Expr>)>, Diagnostic> {
         self.expect(TokenKind::LParen)?;
 
         let mut params = Vec::new();
         if !self.check(TokenKind::RParen) {
             loop {
+                let span = self.span();
                 let p = self.ident()?;
+                self.declare(&p, span, false);
                 let default = if self.match_tok(TokenKind::Eq) {
                     Some(self.expr()?)
                 } else {
@@ -96,158 +344,674 @@ impl Parser {
         }
 
         self.expect(TokenKind::RParen)?;
-        let body = self.block()?;
-        Ok(Node::Function { name, params, body })
+        Ok(params)
     }
 
-    fn return_stmt(&mut self) -> Result<Node, String> {
+    fn return_stmt(&mut self) -> Result<NodeKind, Diagnostic> {
         self.advance();
         if self.match_tok(TokenKind::Semicolon) {
-            Ok(Node::Return(None))
+            Ok(NodeKind::Return(None))
         } else {
             let v = self.expr()?;
             self.terminator()?;
-            Ok(Node::Return(Some(v)))
+            Ok(NodeKind::Return(Some(v)))
         }
     }
 
-    fn while_stmt(&mut self) -> Result<Node, String> {
+    fn while_stmt(&mut self) -> Result<NodeKind, Diagnostic> {
         self.advance();
         self.expect(TokenKind::LParen)?;
-        let cond = self.expr()?;
+        let cond = self.cond()?;
         self.expect(TokenKind::RParen)?;
         while self.match_tok(TokenKind::Semicolon) {}
         let body = self.block()?;
-        Ok(Node::While { cond, body })
+        Ok(NodeKind::While { cond, body })
     }
 
-    fn var_decl(&mut self) -> Result<Node, String> {
+    fn for_stmt(&mut self) -> Result<NodeKind, Diagnostic> {
         self.advance();
+        let paren = self.match_tok(TokenKind::LParen);
+        let span = self.span();
+        let var = self.ident()?;
+        self.expect(TokenKind::In)?;
+        let iter = self.expr()?;
+        if paren {
+            self.expect(TokenKind::RParen)?;
+        }
+        while self.match_tok(TokenKind::Semicolon) {}
+        self.scopes.push(HashMap::new());
+        self.declare(&var, span, false);
+        let body = self.block()?;
+        self.scopes.pop();
+        Ok(NodeKind::For { var, iter, body })
+    }
+
+    fn var_decl(&mut self) -> Result<NodeKind, Diagnostic> {
+        let constant = self.advance().kind == TokenKind::Const;
+        let span = self.span();
         let name = self.ident()?;
         self.expect(TokenKind::Eq)?;
         let value = self.expr()?;
-        self.expect(TokenKind::Semicolon)?;
-        Ok(Node::VarDecl { name, value })
+        self.terminator()?;
+        self.declare(&name, span, constant);
+        Ok(NodeKind::VarDecl {
+            name,
+            value,
+            constant,
+        })
     }
 
-    fn call_or_assign(&mut self) -> Result<Node, String> {
-        let name = self.ident()?;
-        if self.match_tok(TokenKind::Eq) {
+    // A constant can't be declared again in its own scope, and hiding it
+    // behind a local or parameter of the same name gets a warning.
+    fn declare(&mut self, name: &str, span: Span, constant: bool) {
+        let (current, outer) = self.scopes.split_last_mut().unwrap();
+        if let Some(b) = current.get(name).filter(|b| b.constant) {
+            let d = Diagnostic::error("E016", format!("`{}` is already a constant", name), span)
+                .with_hint(format!("it is declared with `const` at line {}", b.line));
+            self.diagnostics.push(d);
+            return;
+        } else if let Some(b) = outer
+            .iter()
+            .rev()
+            .find_map(|s| s.get(name))
+            .filter(|b| b.constant)
+        {
+            let d = Diagnostic::warning("W004", format!("`{}` shadows a constant", name), span)
+                .with_hint(format!(
+                    "the constant is declared at line {}, a different name keeps it visible here",
+                    b.line
+                ));
+            self.diagnostics.push(d);
+        }
+        current.insert(
+            name.to_string(),
+            Binding {
+                constant,
+                line: span.line,
+            },
+        );
+    }
+
+    fn check_assign(&mut self, name: &str, span: Span) {
+        let binding = self.scopes.iter().rev().find_map(|s| s.get(name));
+        if let Some(b) = binding.filter(|b| b.constant) {
+            let d = Diagnostic::error(
+                "E017",
+                format!("cannot assign to constant `{}`", name),
+                span,
+            )
+            .with_hint(format!(
+                "it is declared with `const` at line {}, use `let` if it needs to change",
+                b.line
+            ));
+            self.diagnostics.push(d);
+        }
+    }
+
+    fn call_or_assign(&mut self) -> Result<NodeKind, Diagnostic> {
+        let word = match &self.peek().kind {
+            TokenKind::Ident(s) => s.clone(),
+            _ => String::new(),
+        };
+        // `whlie (x) {` or `var x = 1` parse as something else and fail later on
+        self.assignment_or_call()
+            .map_err(|d| match (&d.hint, keyword_hint(&word)) {
+                (None, Some(hint)) if d.code != "E006" => d.with_hint(hint),
+                _ => d,
+            })
+    }
+
+    fn assignment_or_call(&mut self) -> Result<NodeKind, Diagnostic> {
+        let e = self.primary()?;
+        let op = match self.peek().kind {
+            TokenKind::Eq => Some(None),
+            TokenKind::PlusEq => Some(Some(Op::Add)),
+            TokenKind::MinusEq => Some(Some(Op::Sub)),
+            TokenKind::StarEq => Some(Some(Op::Mul)),
+            TokenKind::SlashEq => Some(Some(Op::Div)),
+            TokenKind::PercentEq => Some(Some(Op::Mod)),
+            _ => None,
+        };
+        let step = matches!(
+            self.peek().kind,
+            TokenKind::PlusPlus | TokenKind::MinusMinus
+        );
+        if let ExprKind::Var(name) = &e.kind
+            && (op.is_some() || step)
+        {
+            let name = name.clone();
+            self.check_assign(&name, e.span);
+        }
+        if let Some(op) = op {
+            let target = self.assign_target(e)?;
+            self.advance();
             let value = self.expr()?;
             self.terminator()?;
-            Ok(Node::Assign { name, value })
-        } else {
-            self.expect(TokenKind::LParen)?;
-            let args = self.args()?;
-            self.expect(TokenKind::RParen)?;
+            return Ok(NodeKind::Assign { target, op, value });
+        }
+
+        let span = self.span();
+        let step = match self.peek().kind {
+            TokenKind::PlusPlus => Some(Op::Add),
+            TokenKind::MinusMinus => Some(Op::Sub),
+            _ => None,
+        };
+        if let Some(op) = step {
+            let target = self.assign_target(e)?;
+            self.advance();
             self.terminator()?;
-            Ok(Node::Call { name, args })
+            let value = Expr::new(ExprKind::Number(1), span);
+            return Ok(NodeKind::Assign {
+                target,
+                op: Some(op),
+                value,
+            });
+        }
+
+        let node = match e.kind {
+            ExprKind::Call { name, args } => NodeKind::Call { name, args },
+            ExprKind::Apply { .. } => NodeKind::Expr(e),
+            _ => {
+                return Err(Diagnostic::error(
+                    "E007",
+                    "expected a function call or assignment",
+                    e.span,
+                ));
+            }
+        };
+        self.terminator()?;
+        Ok(node)
+    }
+
+    fn assign_target(&self, e: Expr) -> Result<AssignTarget, Diagnostic> {
+        match e.kind {
+            ExprKind::Var(name) => Ok(AssignTarget::Var(name)),
+            ExprKind::Index(target, index) => Ok(AssignTarget::Index(*target, *index)),
+            ExprKind::Field(target, field) => Ok(AssignTarget::Field(*target, field)),
+            _ => Err(
+                Diagnostic::error("E006", "invalid assignment target", e.span).with_hint(
+                    "only variables, list elements, map entries and fields can be assigned",
+                ),
+            ),
         }
     }
 
-    fn terminator(&mut self) -> Result<(), String> {
+    fn terminator(&mut self) -> Result<(), Diagnostic> {
         if self.match_tok(TokenKind::Semicolon) {
             return Ok(());
         }
+        // the lexer never inserts a semicolon after `}`, so a literal ending in one ends the statement
+        if self.pos > 0 && self.tokens[self.pos - 1].kind == TokenKind::RBrace {
+            return Ok(());
+        }
         match self.peek().kind {
             TokenKind::RBrace | TokenKind::Else | TokenKind::Elif | TokenKind::Eof => Ok(()),
-            _ => self.err("Expected semicolon"),
+            _ => self.unexpected("E005", "`;` or a new line"),
         }
     }
 
-    fn if_stmt(&mut self) -> Result<Node, String> {
+    fn if_stmt(&mut self) -> Result<NodeKind, Diagnostic> {
         self.expect(TokenKind::If)?;
         self.expect(TokenKind::LParen)?;
-        let cond = self.expr()?;
+        let cond = self.cond()?;
         self.expect(TokenKind::RParen)?;
         while self.match_tok(TokenKind::Semicolon) {}
         let then_body = self.block()?;
 
         while self.match_tok(TokenKind::Semicolon) {}
 
+        let span = self.span();
         let else_body = if self.match_tok(TokenKind::Elif) {
-            let elif_node = self.elif_chain()?;
-            Some(vec![elif_node])
+            let kind = self.elif_chain()?;
+            Some(vec![Node { kind, span }])
         } else if self.match_tok(TokenKind::Else) {
+            if self.check(TokenKind::If) {
+                let d = Diagnostic::error("E002", "expected `{`, found `if`", self.span());
+                return Err(d.with_hint("did you mean `elif`?"));
+            }
             Some(self.block()?)
         } else {
             None
         };
 
-        Ok(Node::If {
+        Ok(NodeKind::If {
             cond,
             then_body,
             else_body,
         })
     }
 
-    fn elif_chain(&mut self) -> Result<Node, String> {
+    fn elif_chain(&mut self) -> Result<NodeKind, Diagnostic> {
         self.expect(TokenKind::LParen)?;
-        let cond = self.expr()?;
+        let cond = self.cond()?;
         self.expect(TokenKind::RParen)?;
         while self.match_tok(TokenKind::Semicolon) {}
         let then_body = self.block()?;
 
         while self.match_tok(TokenKind::Semicolon) {}
 
+        let span = self.span();
         let else_body = if self.match_tok(TokenKind::Elif) {
-            Some(vec![self.elif_chain()?])
+            let kind = self.elif_chain()?;
+            Some(vec![Node { kind, span }])
         } else if self.match_tok(TokenKind::Else) {
+            if self.check(TokenKind::If) {
+                let d = Diagnostic::error("E002", "expected `{`, found `if`", self.span());
+                return Err(d.with_hint("did you mean `elif`?"));
+            }
             Some(self.block()?)
         } else {
             None
         };
 
-        Ok(Node::If {
+        Ok(NodeKind::If {
             cond,
             then_body,
             else_body,
         })
     }
 
-    fn loop_stmt(&mut self) -> Result<Node, String> {
+    fn try_stmt(&mut self) -> Result<NodeKind, Diagnostic> {
+        self.advance();
+        while self.match_tok(TokenKind::Semicolon) {}
+        let body = self.block()?;
+        while self.match_tok(TokenKind::Semicolon) {}
+
+        let catch = if self.match_tok(TokenKind::Catch) {
+            self.scopes.push(HashMap::new());
+            let name = if self.match_tok(TokenKind::LParen) {
+                let span = self.span();
+                let name = self.ident()?;
+                self.declare(&name, span, false);
+                self.expect(TokenKind::RParen)?;
+                Some(name)
+            } else {
+                None
+            };
+            while self.match_tok(TokenKind::Semicolon) {}
+            let handler = self.block()?;
+            self.scopes.pop();
+            while self.match_tok(TokenKind::Semicolon) {}
+            Some((name, handler))
+        } else {
+            None
+        };
+
+        let finally = if self.match_tok(TokenKind::Finally) {
+            while self.match_tok(TokenKind::Semicolon) {}
+            Some(self.block()?)
+        } else {
+            None
+        };
+
+        if catch.is_none() && finally.is_none() {
+            // the body parsed fine, so report and carry on with the next statement
+            let t = &self.tokens[self.pos - 1];
+            let span = Span {
+                line: t.line,
+                col: t.col,
+            };
+            let d = Diagnostic::error("E008", "`try` block without `catch` or `finally`", span);
+            self.diagnostics.push(d);
+        }
+
+        Ok(NodeKind::Try {
+            body,
+            catch,
+            finally,
+        })
+    }
+
+    // match (value) { "a", "b" => {...}, 1..5 => {...}, _ => {...} }
+    fn match_stmt(&mut self) -> Result<NodeKind, Diagnostic> {
+        let span = self.span();
+        self.advance();
+        self.expect(TokenKind::LParen)?;
+        let subject = self.expr()?;
+        self.expect(TokenKind::RParen)?;
+        while self.match_tok(TokenKind::Semicolon) {}
+        self.expect(TokenKind::LBrace)?;
+
+        let mut arms = Vec::new();
+        let mut catch_all = false;
+        let mut warned = false;
+        loop {
+            while self.match_tok(TokenKind::Semicolon) {}
+            if self.check(TokenKind::RBrace) || self.check(TokenKind::Eof) {
+                break;
+            }
+
+            let arm_span = self.span();
+            let patterns = self.patterns()?;
+            self.expect(TokenKind::FatArrow)?;
+            while self.match_tok(TokenKind::Semicolon) {}
+            let body = self.block()?;
+
+            if catch_all && !warned {
+                warned = true;
+                let d = Diagnostic::warning("W003", "unreachable match arm", arm_span)
+                    .with_hint("the `_` arm above already matches everything");
+                self.diagnostics.push(d);
+            }
+            catch_all |= patterns.iter().any(|p| matches!(p, Pattern::Wildcard));
+            arms.push((patterns, body));
+
+            while self.match_tok(TokenKind::Semicolon) {}
+            self.match_tok(TokenKind::Comma);
+        }
+        self.expect(TokenKind::RBrace)?;
+
+        self.check_exhaustive(&arms, span);
+        Ok(NodeKind::Match { subject, arms })
+    }
+
+    // select { recv(rx) as msg => {...}, key(F6) => {...}, timeout(500) => {...} }
+    fn select_stmt(&mut self) -> Result<NodeKind, Diagnostic> {
+        let span = self.span();
+        self.advance();
+        while self.match_tok(TokenKind::Semicolon) {}
+        self.expect(TokenKind::LBrace)?;
+
+        let mut arms = Vec::new();
+        let mut timeout = false;
+        loop {
+            while self.match_tok(TokenKind::Semicolon) {}
+            if self.check(TokenKind::RBrace) || self.check(TokenKind::Eof) {
+                break;
+            }
+
+            let arm_span = self.span();
+            let kind = match &self.peek().kind {
+                TokenKind::Ident(s) if matches!(s.as_str(), "recv" | "key" | "timeout") => {
+                    s.clone()
+                }
+                _ => {
+                    let found = self.peek().kind.describe();
+                    let msg = format!("expected `recv`, `key` or `timeout`, found {}", found);
+                    let d = Diagnostic::error("E022", msg, arm_span);
+                    return Err(d.with_hint("a select arm looks like `recv(rx) as msg => { ... }`"));
+                }
+            };
+            self.advance();
+            self.expect(TokenKind::LParen)?;
+            let e = self.expr()?;
+            self.expect(TokenKind::RParen)?;
+
+            // the received value is only visible inside its arm
+            self.scopes.push(HashMap::new());
+            let arm = match kind.as_str() {
+                "recv" => {
+                    let var = if matches!(&self.peek().kind, TokenKind::Ident(s) if s == "as") {
+                        self.advance();
+                        let at = self.span();
+                        let name = self.ident()?;
+                        self.declare(&name, at, false);
+                        Some(name)
+                    } else {
+                        None
+                    };
+                    SelectArm::Recv { from: e, var }
+                }
+                "key" => SelectArm::Key(e),
+                _ => {
+                    if timeout {
+                        let d = Diagnostic::error(
+                            "E022",
+                            "`select` has more than one `timeout`",
+                            arm_span,
+                        );
+                        self.diagnostics.push(d);
+                    }
+                    timeout = true;
+                    SelectArm::Timeout(e)
+                }
+            };
+            let body = self.expect(TokenKind::FatArrow).and_then(|_| {
+                while self.match_tok(TokenKind::Semicolon) {}
+                self.block()
+            });
+            self.scopes.pop();
+            arms.push((arm, body?));
+
+            while self.match_tok(TokenKind::Semicolon) {}
+            self.match_tok(TokenKind::Comma);
+        }
+        self.expect(TokenKind::RBrace)?;
+
+        if arms.is_empty() {
+            let d = Diagnostic::error("E022", "`select` without any arms", span);
+            return Err(d.with_hint("it would wait forever"));
+        }
+        Ok(NodeKind::Select { arms })
+    }
+
+    fn patterns(&mut self) -> Result<Vec<Pattern>, Diagnostic> {
+        let mut patterns = vec![self.pattern()?];
+        while self.match_tok(TokenKind::Comma) {
+            while self.match_tok(TokenKind::Semicolon) {}
+            patterns.push(self.pattern()?);
+        }
+        Ok(patterns)
+    }
+
+    fn pattern(&mut self) -> Result<Pattern, Diagnostic> {
+        if self.peek().kind == TokenKind::Ident("_".into()) {
+            self.advance();
+            return Ok(Pattern::Wildcard);
+        }
+
+        let start = self.expr()?;
+        let inclusive = if self.match_tok(TokenKind::DotDot) {
+            false
+        } else if self.match_tok(TokenKind::DotDotEq) {
+            true
+        } else {
+            return Ok(Pattern::Value(start));
+        };
+        let end = self.expr()?;
+        Ok(Pattern::Range {
+            start,
+            end,
+            inclusive,
+        })
+    }
+
+    // True/false and whole-number arms are the sets the parser can see: a
+    // bool match missing one side, or integer arms that leave a gap between
+    // them, gets a warning. Other matches without `_` quietly do nothing
+    // when no arm fits.
+    fn check_exhaustive(&mut self, arms: &[(Vec<Pattern>, Vec<Node>)], span: Span) {
+        let patterns: Vec<&Pattern> = arms.iter().flat_map(|(p, _)| p).collect();
+        let missing = match Self::bool_gap(&patterns) {
+            Some(missing) => missing,
+            None => match Self::integer_gap(&patterns) {
+                Some(missing) => missing,
+                None => return,
+            },
+        };
+        let d = Diagnostic::warning("W002", format!("match does not cover `{}`", missing), span)
+            .with_hint(format!("add a `{}` arm or a `_` arm", missing));
+        self.diagnostics.push(d);
+    }
+
+    fn bool_gap(patterns: &[&Pattern]) -> Option<String> {
+        let mut seen = [false, false];
+        for p in patterns {
+            match p {
+                Pattern::Value(Expr {
+                    kind: ExprKind::Bool(b),
+                    ..
+                }) => seen[*b as usize] = true,
+                _ => return None,
+            }
+        }
+        match seen {
+            [false, true] => Some("false".into()),
+            [true, false] => Some("true".into()),
+            _ => None,
+        }
+    }
+
+    // The first run of whole numbers between the lowest and highest arm
+    // that no arm takes, when every arm is an integer or integer range.
+    fn integer_gap(patterns: &[&Pattern]) -> Option<String> {
+        fn int(e: &Expr) -> Option<i128> {
+            match &e.kind {
+                ExprKind::Number(n) => Some(*n as i128),
+                ExprKind::Unary(Op::Sub, inner) => int(inner).map(|n| -n),
+                _ => None,
+            }
+        }
+
+        // half-open [lo, hi) spans
+        let mut spans = Vec::new();
+        for p in patterns {
+            match p {
+                Pattern::Value(e) => {
+                    let n = int(e)?;
+                    spans.push((n, n + 1));
+                }
+                Pattern::Range {
+                    start,
+                    end,
+                    inclusive,
+                } => spans.push((int(start)?, int(end)? + *inclusive as i128)),
+                Pattern::Wildcard => return None,
+            }
+        }
+        spans.sort();
+
+        let mut covered = spans.first()?.1;
+        for &(lo, hi) in &spans[1..] {
+            if lo > covered {
+                return Some(if lo == covered + 1 {
+                    covered.to_string()
+                } else {
+                    format!("{}..{}", covered, lo)
+                });
+            }
+            covered = covered.max(hi);
+        }
+        None
+    }
+
+    fn loop_stmt(&mut self) -> Result<NodeKind, Diagnostic> {
         self.advance();
         self.expect(TokenKind::LParen)?;
         let times = self.expr()?;
         self.expect(TokenKind::RParen)?;
         while self.match_tok(TokenKind::Semicolon) {}
         let body = self.block()?;
-        Ok(Node::Loop { times, body })
+        Ok(NodeKind::Loop { times, body })
     }
 
-    fn block(&mut self) -> Result<Vec<Node>, String> {
+    fn block(&mut self) -> Result<Vec<Node>, Diagnostic> {
+        self.scopes.push(HashMap::new());
+        let body = self.block_body();
+        self.scopes.pop();
+        body
+    }
+
+    fn block_body(&mut self) -> Result<Vec<Node>, Diagnostic> {
+        let open = self.span();
         self.expect(TokenKind::LBrace)?;
-        let mut nodes = Vec::new();
-        while !self.check(TokenKind::RBrace) && !self.check(TokenKind::Eof) {
-            nodes.push(self.stmt()?);
+        let mut nodes: Vec<Node> = Vec::new();
+        let mut warned = false;
+        loop {
+            while self.match_tok(TokenKind::Semicolon) {}
+            if self.check(TokenKind::RBrace) || self.check(TokenKind::Eof) {
+                break;
+            }
+            let jumped = nodes.last().is_some_and(|n| {
+                matches!(
+                    n.kind,
+                    NodeKind::Break | NodeKind::Continue | NodeKind::Return(_)
+                )
+            });
+            if jumped && !warned {
+                warned = true;
+                let d = Diagnostic::warning("W001", "unreachable statement", self.span());
+                self.diagnostics.push(d);
+            }
+            if let Some(n) = self.recover_stmt() {
+                nodes.push(n);
+            }
+        }
+        if self.check(TokenKind::Eof) {
+            let d = Diagnostic::error("E002", "expected `}`, found end of file", self.span());
+            return Err(d.with_hint(format!(
+                "the block opened at line {} is never closed",
+                open.line
+            )));
         }
         self.expect(TokenKind::RBrace)?;
         Ok(nodes)
     }
 
-    fn expr(&mut self) -> Result<Expr, String> {
-        self.logic_or()
+    fn expr(&mut self) -> Result<Expr, Diagnostic> {
+        self.ternary()
+    }
+
+    fn cond(&mut self) -> Result<Expr, Diagnostic> {
+        let e = self.expr()?;
+        Ok(self.truthy(e))
     }
 
-    fn logic_or(&mut self) -> Result<Expr, String> {
+    // wraps a value used as a condition when the file opted into truthiness
+    fn truthy(&self, e: Expr) -> Expr {
+        if !self.truthy {
+            return e;
+        }
+        let span = e.span;
+        Expr::new(ExprKind::Truthy(Box::new(e)), span)
+    }
+
+    // a ? b : c ? d : e groups as a ? b : (c ? d : e)
+    fn ternary(&mut self) -> Result<Expr, Diagnostic> {
+        let e = self.logic_or()?;
+        let span = self.span();
+        if !self.match_tok(TokenKind::Question) {
+            return Ok(e);
+        }
+        let cond = self.truthy(e);
+        let then = self.expr()?;
+        self.expect(TokenKind::Colon)?;
+        let otherwise = self.expr()?;
+        Ok(Expr::new(
+            ExprKind::Cond(Box::new(cond), Box::new(then), Box::new(otherwise)),
+            span,
+        ))
+    }
+
+    fn logic_or(&mut self) -> Result<Expr, Diagnostic> {
         let mut e = self.logic_and()?;
-        while self.match_tok(TokenKind::OrOr) {
+        loop {
+            let span = self.span();
+            if !self.match_tok(TokenKind::OrOr) {
+                break;
+            }
             let r = self.logic_and()?;
-            e = Expr::Binary(Box::new(e), Op::Or, Box::new(r));
+            let (l, r) = (self.truthy(e), self.truthy(r));
+            e = Expr::new(ExprKind::Binary(Box::new(l), Op::Or, Box::new(r)), span);
         }
         Ok(e)
     }
 
-    fn logic_and(&mut self) -> Result<Expr, String> {
+    fn logic_and(&mut self) -> Result<Expr, Diagnostic> {
         let mut e = self.equality()?;
-        while self.match_tok(TokenKind::AndAnd) {
+        loop {
+            let span = self.span();
+            if !self.match_tok(TokenKind::AndAnd) {
+                break;
+            }
             let r = self.equality()?;
-            e = Expr::Binary(Box::new(e), Op::And, Box::new(r));
+            let (l, r) = (self.truthy(e), self.truthy(r));
+            e = Expr::new(ExprKind::Binary(Box::new(l), Op::And, Box::new(r)), span);
         }
         Ok(e)
     }
 
-    fn equality(&mut self) -> Result<Expr, String> {
+    fn equality(&mut self) -> Result<Expr, Diagnostic> {
         let mut e = self.compare()?;
         loop {
             let op = match &self.peek().kind {
@@ -255,14 +1019,15 @@ impl Parser {
                 TokenKind::Ne => Op::Ne,
                 _ => break,
             };
+            let span = self.span();
             self.advance();
             let r = self.compare()?;
-            e = Expr::Binary(Box::new(e), op, Box::new(r));
+            e = Expr::new(ExprKind::Binary(Box::new(e), op, Box::new(r)), span);
         }
         Ok(e)
     }
 
-    fn compare(&mut self) -> Result<Expr, String> {
+    fn compare(&mut self) -> Result<Expr, Diagnostic> {
         let mut e = self.term()?;
         loop {
             let op = match &self.peek().kind {
@@ -272,14 +1037,15 @@ impl Parser {
                 TokenKind::Le => Op::Le,
                 _ => break,
             };
+            let span = self.span();
             self.advance();
             let r = self.term()?;
-            e = Expr::Binary(Box::new(e), op, Box::new(r));
+            e = Expr::new(ExprKind::Binary(Box::new(e), op, Box::new(r)), span);
         }
         Ok(e)
     }
 
-    fn term(&mut self) -> Result<Expr, String> {
+    fn term(&mut self) -> Result<Expr, Diagnostic> {
         let mut e = self.factor()?;
         loop {
             let op = match &self.peek().kind {
@@ -287,72 +1053,189 @@ impl Parser {
                 TokenKind::Minus => Op::Sub,
                 _ => break,
             };
+            let span = self.span();
             self.advance();
             let r = self.factor()?;
-            e = Expr::Binary(Box::new(e), op, Box::new(r));
+            e = Expr::new(ExprKind::Binary(Box::new(e), op, Box::new(r)), span);
         }
         Ok(e)
     }
 
-    fn factor(&mut self) -> Result<Expr, String> {
+    fn factor(&mut self) -> Result<Expr, Diagnostic> {
         let mut e = self.unary()?;
         loop {
             let op = match &self.peek().kind {
                 TokenKind::Star => Op::Mul,
                 TokenKind::Slash => Op::Div,
+                TokenKind::Percent => Op::Mod,
                 _ => break,
             };
+            let span = self.span();
             self.advance();
             let r = self.unary()?;
-            e = Expr::Binary(Box::new(e), op, Box::new(r));
+            e = Expr::new(ExprKind::Binary(Box::new(e), op, Box::new(r)), span);
         }
         Ok(e)
     }
 
-    fn unary(&mut self) -> Result<Expr, String> {
+    fn unary(&mut self) -> Result<Expr, Diagnostic> {
+        let span = self.span();
         if self.match_tok(TokenKind::Minus) {
-            Ok(Expr::Unary(Op::Sub, Box::new(self.unary()?)))
+            let power =
+                self.tokens.get(self.pos + 1).map(|t| &t.kind) == Some(&TokenKind::StarStar);
+            if self.check(TokenKind::Number(i64::MIN)) && !power {
+                self.advance();
+                return Ok(Expr::new(ExprKind::Number(i64::MIN), span));
+            }
+            let e = self.unary()?;
+            Ok(Expr::new(ExprKind::Unary(Op::Sub, Box::new(e)), span))
         } else if self.match_tok(TokenKind::Bang) {
-            Ok(Expr::Unary(Op::Not, Box::new(self.unary()?)))
+            let e = self.unary()?;
+            let e = self.truthy(e);
+            Ok(Expr::new(ExprKind::Unary(Op::Not, Box::new(e)), span))
         } else {
-            self.primary()
+            self.power()
         }
     }
 
-    fn primary(&mut self) -> Result<Expr, String> {
-        match &self.advance().kind {
-            TokenKind::Number(n) => Ok(Expr::Number(*n)),
-            TokenKind::Str(s) => Ok(Expr::Str(s.clone())),
-            TokenKind::True => Ok(Expr::Bool(true)),
-            TokenKind::False => Ok(Expr::Bool(false)),
+    // binds tighter than a leading minus (-2 ** 2 is -4) and groups to the
+    // right (2 ** 3 ** 2 is 2 ** 9)
+    fn power(&mut self) -> Result<Expr, Diagnostic> {
+        let e = self.primary()?;
+        let span = self.span();
+        if !self.match_tok(TokenKind::StarStar) {
+            return Ok(e);
+        }
+        let r = self.unary()?;
+        Ok(Expr::new(
+            ExprKind::Binary(Box::new(e), Op::Pow, Box::new(r)),
+            span,
+        ))
+    }
+
+    fn primary(&mut self) -> Result<Expr, Diagnostic> {
+        let mut e = self.atom()?;
+        loop {
+            let span = self.span();
+            if self.check(TokenKind::LBracket) {
+                let index = self.index()?;
+                e = Expr::new(ExprKind::Index(Box::new(e), Box::new(index)), span);
+            } else if self.match_tok(TokenKind::LParen) {
+                let args = self.args()?;
+                self.expect(TokenKind::RParen)?;
+                let callee = Box::new(e);
+                e = Expr::new(ExprKind::Apply { callee, args }, span);
+            } else if self.match_tok(TokenKind::Dot) {
+                let field = self.ident()?;
+                e = Expr::new(ExprKind::Field(Box::new(e), field), span);
+            } else {
+                return Ok(e);
+            }
+        }
+    }
+
+    fn index(&mut self) -> Result<Expr, Diagnostic> {
+        self.expect(TokenKind::LBracket)?;
+        let e = self.expr()?;
+        self.expect(TokenKind::RBracket)?;
+        Ok(e)
+    }
+
+    fn atom(&mut self) -> Result<Expr, Diagnostic> {
+        let span = self.span();
+        let kind = match &self.advance().kind {
+            // i64::MIN's digits without the minus in front, as in `a - 9223372036854775808`
+            TokenKind::Number(i64::MIN) => {
+                let d = Diagnostic::error("E011", "number is too large", span).with_hint(format!(
+                    "integers go up to {}, add `.0` for a float",
+                    i64::MAX
+                ));
+                return Err(d);
+            }
+            TokenKind::Number(n) => ExprKind::Number(*n),
+            TokenKind::Float(n) => ExprKind::Float(*n),
+            TokenKind::Str(s) => ExprKind::Str(s.clone()),
+            TokenKind::Template(parts) => {
+                let parts = parts.clone();
+                return self.template(parts, span);
+            }
+            TokenKind::True => ExprKind::Bool(true),
+            TokenKind::False => ExprKind::Bool(false),
             TokenKind::Ident(s) => {
-                let mut name = s.clone();
-                while self.match_tok(TokenKind::Dot) {
-                    if let TokenKind::Ident(n) = &self.advance().kind {
-                        name.push('.');
-                        name.push_str(n);
-                    } else {
-                        return self.err("Expected identifier");
-                    }
-                }
+                let name = s.clone();
                 if self.match_tok(TokenKind::LParen) {
                     let args = self.args()?;
                     self.expect(TokenKind::RParen)?;
-                    Ok(Expr::Call { name, args })
+                    ExprKind::Call { name, args }
                 } else {
-                    Ok(Expr::Var(name))
+                    ExprKind::Var(name)
                 }
             }
             TokenKind::LParen => {
                 let e = self.expr()?;
                 self.expect(TokenKind::RParen)?;
-                Ok(e)
+                return Ok(e);
+            }
+            TokenKind::Async => {
+                while self.match_tok(TokenKind::Semicolon) {}
+                ExprKind::Async(self.block()?)
+            }
+            TokenKind::Fn => {
+                self.scopes.push(HashMap::new());
+                let params = self.params()?;
+                let body = self.block()?;
+                self.scopes.pop();
+                ExprKind::Lambda { params, body }
+            }
+            TokenKind::LBracket => {
+                let items = self.list_items()?;
+                self.expect(TokenKind::RBracket)?;
+                ExprKind::List(items)
+            }
+            TokenKind::LBrace => {
+                let entries = self.map_entries()?;
+                self.expect(TokenKind::RBrace)?;
+                ExprKind::Map(entries)
             }
-            _ => self.err("Invalid expression"),
+            k => {
+                let msg = format!("expected an expression, found {}", k.describe());
+                // left in place, so recovery restarts at a statement like `if`
+                self.pos -= 1;
+                return Err(Diagnostic::error("E004", msg, span));
+            }
+        };
+        Ok(Expr::new(kind, span))
+    }
+
+    // "a{x}b" becomes "a" + x + "b"; starting from a string keeps `+` a
+    // concatenation even when the first placeholder is a number
+    fn template(&mut self, parts: Vec<TemplatePart>, span: Span) -> Result<Expr, Diagnostic> {
+        let mut e: Option<Expr> = None;
+        for part in parts {
+            let (next, at) = match part {
+                TemplatePart::Text(s) if e.is_some() && s.is_empty() => continue,
+                TemplatePart::Text(s) => (Expr::new(ExprKind::Str(s), span), span),
+                TemplatePart::Code(tokens) => {
+                    let mut sub = Parser::new(tokens);
+                    sub.truthy = self.truthy;
+                    sub.scopes = self.scopes.clone();
+                    let code = sub.expr();
+                    self.diagnostics.append(&mut sub.diagnostics);
+                    let code = code?;
+                    sub.expect(TokenKind::RBrace)?;
+                    let at = code.span;
+                    (code, at)
+                }
+            };
+            e = Some(match e {
+                None => next,
+                Some(l) => Expr::new(ExprKind::Binary(Box::new(l), Op::Add, Box::new(next)), at),
+            });
         }
+        Ok(e.unwrap_or(Expr::new(ExprKind::Str(String::new()), span)))
     }
 
-    fn args(&mut self) -> Result<Vec<Expr>, String> {
+    fn args(&mut self) -> Result<Vec<Expr>, Diagnostic> {
         let mut a = Vec::new();
         if !self.check(TokenKind::RParen) {
             a.push(self.expr()?);
@@ -363,18 +1246,62 @@ impl Parser {
         Ok(a)
     }
 
-    fn ident(&mut self) -> Result<String, String> {
-        match &self.advance().kind {
-            TokenKind::Ident(s) => Ok(s.clone()),
-            _ => self.err("Expected identifier"),
+    // newlines inside a literal may have been turned into semicolons by the lexer
+    fn list_items(&mut self) -> Result<Vec<Expr>, Diagnostic> {
+        let mut items = Vec::new();
+        while self.match_tok(TokenKind::Semicolon) {}
+        while !self.check(TokenKind::RBracket) {
+            items.push(self.expr()?);
+            while self.match_tok(TokenKind::Semicolon) {}
+            if !self.match_tok(TokenKind::Comma) {
+                break;
+            }
+            while self.match_tok(TokenKind::Semicolon) {}
         }
+        Ok(items)
     }
 
-    fn expect(&mut self, k: TokenKind) -> Result<(), String> {
+    fn map_entries(&mut self) -> Result<Vec<(Expr, Expr)>, Diagnostic> {
+        let mut entries = Vec::new();
+        while self.match_tok(TokenKind::Semicolon) {}
+        while !self.check(TokenKind::RBrace) {
+            let key = self.expr()?;
+            self.expect(TokenKind::Colon)?;
+            let value = self.expr()?;
+            entries.push((key, value));
+            while self.match_tok(TokenKind::Semicolon) {}
+            if !self.match_tok(TokenKind::Comma) {
+                break;
+            }
+            while self.match_tok(TokenKind::Semicolon) {}
+        }
+        Ok(entries)
+    }
+
+    fn ident(&mut self) -> Result<String, Diagnostic> {
+        match &self.peek().kind {
+            TokenKind::Ident(s) => {
+                let s = s.clone();
+                self.advance();
+                Ok(s)
+            }
+            _ => self.unexpected("E003", "an identifier"),
+        }
+    }
+
+    fn expect(&mut self, k: TokenKind) -> Result<(), Diagnostic> {
         if self.match_tok(k.clone()) {
             Ok(())
         } else {
-            self.err(&format!("Expected {:?}", k))
+            self.unexpected("E002", &k.describe())
+        }
+    }
+
+    fn span(&self) -> Span {
+        let t = self.peek();
+        Span {
+            line: t.line,
+            col: t.col,
         }
     }
 
@@ -405,3 +1332,171 @@ impl Parser {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    fn parse(src: &str) -> (Vec<Node>, Vec<Diagnostic>) {
+        let (tokens, diagnostics) = Lexer::new(src).tokenize();
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        Parser::new(tokens).parse()
+    }
+
+    // code, line and column of every diagnostic, in order
+    fn errors(src: &str) -> Vec<(&'static str, usize, usize)> {
+        let (_, diagnostics) = parse(src);
+        diagnostics
+            .iter()
+            .map(|d| (d.code, d.span.line, d.span.col))
+            .collect()
+    }
+
+    #[test]
+    fn a_missing_expression_leaves_the_next_statement_alone() {
+        let src = "let a =\nif (true) { print(1) } else { print(2) }";
+        let (nodes, diagnostics) = parse(src);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].message, "expected an expression, found `if`");
+        assert!(matches!(
+            nodes.as_slice(),
+            [Node {
+                kind: NodeKind::If { .. },
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn every_broken_statement_in_a_file_is_reported_once() {
+        let src = "\
+let a = 1 +
+print(a
+let b = 2
+while (true { }
+let c = )
+print(b)";
+        assert_eq!(
+            errors(src),
+            // `1 +` goes on to the next line, which lacks its `)`
+            [("E002", 2, 8), ("E002", 4, 13), ("E004", 5, 9)]
+        );
+    }
+
+    #[test]
+    fn else_if_suggests_elif() {
+        let (_, diagnostics) = parse("let a = true\nif (a) { } else if (a) { }");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].code, "E002");
+        assert_eq!(diagnostics[0].hint.as_deref(), Some("did you mean `elif`?"));
+    }
+
+    #[test]
+    fn a_stray_closing_brace_is_skipped() {
+        assert_eq!(errors("}\nprint(1)\n}"), [("E001", 1, 1), ("E001", 3, 1)]);
+    }
+
+    fn warnings(src: &str) -> Vec<(&'static str, String)> {
+        let (_, diagnostics) = parse(src);
+        diagnostics
+            .into_iter()
+            .map(|d| (d.code, d.message))
+            .collect()
+    }
+
+    #[test]
+    fn a_bool_match_missing_a_side_warns() {
+        assert_eq!(
+            warnings("match (true) { true => { } }"),
+            [("W002", "match does not cover `false`".to_string())]
+        );
+        assert!(warnings("match (true) { true => { } false => { } }").is_empty());
+    }
+
+    #[test]
+    fn integer_arms_that_leave_a_gap_warn() {
+        assert_eq!(
+            warnings("match (1) { 0..960 => { } 970..=1920 => { } }"),
+            [("W002", "match does not cover `960..970`".to_string())]
+        );
+        assert_eq!(
+            warnings("match (1) { -1 => { } 1, 2 => { } }"),
+            [("W002", "match does not cover `0`".to_string())]
+        );
+        assert!(warnings("match (1) { 0..960 => { } 960..=1920 => { } }").is_empty());
+        assert!(warnings("match (1) { 1..=5 => { } 2 => { } 6 => { } }").is_empty());
+    }
+
+    #[test]
+    fn a_wildcard_or_other_values_skip_the_check() {
+        assert!(warnings("match (1) { 1 => { } 3 => { } _ => { } }").is_empty());
+        assert!(warnings("match (\"a\") { \"a\" => { } \"c\" => { } }").is_empty());
+    }
+
+    #[test]
+    fn arms_after_a_wildcard_are_unreachable() {
+        let src = "match (1) { _ => { } 1 => { } 2 => { } }";
+        assert_eq!(
+            warnings(src),
+            [("W003", "unreachable match arm".to_string())]
+        );
+        assert_eq!(errors(src), [("W003", 1, 22)]);
+    }
+
+    #[test]
+    fn the_smallest_integer_only_parses_with_its_minus() {
+        let (nodes, diagnostics) = parse("let a = -9223372036854775808");
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        assert!(matches!(
+            &nodes[0].kind,
+            NodeKind::VarDecl {
+                value: Expr {
+                    kind: ExprKind::Number(i64::MIN),
+                    ..
+                },
+                ..
+            }
+        ));
+        assert_eq!(errors("let a = 1 -9223372036854775808"), [("E011", 1, 12)]);
+    }
+
+    #[test]
+    fn a_constant_cannot_be_declared_twice_or_assigned() {
+        assert_eq!(errors("const a = 1\nconst a = 2"), [("E016", 2, 7)]);
+        assert_eq!(errors("const a = 1\nlet a = 2"), [("E016", 2, 5)]);
+        assert_eq!(
+            errors("const a = 1\na = 2\na += 1\na++\ndef f() { a = 3 }"),
+            [
+                ("E017", 2, 1),
+                ("E017", 3, 1),
+                ("E017", 4, 1),
+                ("E017", 5, 11)
+            ]
+        );
+        let (_, diagnostics) = parse("const key = 1\nkey = 2");
+        assert_eq!(diagnostics[0].message, "cannot assign to constant `key`");
+        assert_eq!(
+            diagnostics[0].hint.as_deref(),
+            Some("it is declared with `const` at line 1, use `let` if it needs to change")
+        );
+    }
+
+    #[test]
+    fn hiding_a_constant_is_a_warning() {
+        assert_eq!(errors("const key = 1\ndef f(key) { }"), [("W004", 2, 7)]);
+        assert_eq!(
+            errors("const key = 1\nif (true) { let key = 2; key = 3 }"),
+            [("W004", 2, 17)]
+        );
+        assert_eq!(
+            errors("const key = 1\nfor key in [1] { }"),
+            [("W004", 2, 5)]
+        );
+        // a name used again after the block is the constant
+        assert_eq!(
+            errors("const key = 1\nif (true) { let key = 2 }\nkey = 3"),
+            [("W004", 2, 17), ("E017", 3, 1)]
+        );
+    }
+}
@@ -0,0 +1,348 @@
+use crate::task::{State, Task};
+use corosensei::stack::DefaultStack;
+use corosensei::{Coroutine, CoroutineResult, Yielder};
+use std::cell::Cell;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, VecDeque};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::Ordering::SeqCst;
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Tasks are coroutines with stacks of their own, run by a few worker
+// threads. A task keeps its worker until it waits for something (sleep,
+// await, recv, join, a lock) or has looped for a whole time slice while
+// other tasks are ready, then the worker moves on to the next task in line.
+// Built-ins that block, like beep or process, still hold a worker while they
+// run, which is why there is more than one.
+const WORKERS: usize = 4;
+// how long a busy task runs before it lets a ready one have its worker
+const SLICE: Duration = Duration::from_millis(10);
+// the same as a thread spawned by std, the memory is only used as it is touched
+const STACK_SIZE: usize = 2 * 1024 * 1024;
+
+// what a suspended task hands its worker: when to wake it if nothing else does
+type Wake = Option<Instant>;
+
+pub struct Fiber(Coroutine<(), Wake, ()>);
+
+// Only interpreter code runs on these stacks, and nothing it keeps across a
+// wait is tied to a thread: the task is looked up again after every wait and
+// no lock guard is held while waiting.
+unsafe impl Send for Fiber {}
+
+#[derive(Default)]
+pub struct Sched {
+    queued: bool,
+    running: bool,
+    // woken while running, so it goes straight back into the queue
+    notified: bool,
+    done: bool,
+}
+
+thread_local! {
+    // when the worker last resumed the task it runs, None when idle
+    static SLICE_START: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+struct Pool {
+    queue: Mutex<VecDeque<Arc<Task>>>,
+    ready: Condvar,
+    timers: Mutex<BinaryHeap<Reverse<Timer>>>,
+    timer_added: Condvar,
+}
+
+struct Timer {
+    at: Instant,
+    task: Arc<Task>,
+}
+
+impl PartialEq for Timer {
+    fn eq(&self, other: &Self) -> bool {
+        self.at == other.at
+    }
+}
+
+impl Eq for Timer {}
+
+impl PartialOrd for Timer {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Timer {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.at.cmp(&other.at)
+    }
+}
+
+fn pool() -> &'static Pool {
+    static POOL: OnceLock<Pool> = OnceLock::new();
+    POOL.get_or_init(|| {
+        for _ in 0..WORKERS {
+            thread::spawn(work);
+        }
+        thread::spawn(fire_timers);
+        Pool {
+            queue: Mutex::new(VecDeque::new()),
+            ready: Condvar::new(),
+            timers: Mutex::new(BinaryHeap::new()),
+            timer_added: Condvar::new(),
+        }
+    })
+}
+
+pub fn spawn(task: &Arc<Task>, f: impl FnOnce() + Send + 'static) {
+    let stack = DefaultStack::new(STACK_SIZE).expect("cannot allocate a task stack");
+    let t = task.clone();
+    let coroutine = Coroutine::with_stack(stack, move |yielder: &Yielder<(), Wake>, ()| {
+        t.yielder.store(yielder as *const _ as *mut (), SeqCst);
+        // a panicking built-in ends the task, not the worker
+        if panic::catch_unwind(AssertUnwindSafe(f)).is_err() {
+            t.finish(State::Failed("task panicked".into()));
+        }
+    });
+    *task.fiber.lock().unwrap() = Some(Fiber(coroutine));
+    wake(task);
+}
+
+// puts the task in the queue unless it is there already or running
+pub fn wake(task: &Arc<Task>) {
+    let mut sched = task.sched.lock().unwrap();
+    if sched.done {
+        return;
+    }
+    if sched.running {
+        sched.notified = true;
+        return;
+    }
+    if !sched.queued {
+        sched.queued = true;
+        drop(sched);
+        let pool = pool();
+        pool.queue.lock().unwrap().push_back(task.clone());
+        pool.ready.notify_one();
+    }
+}
+
+// called by the running task itself, returns once it has been woken
+pub fn suspend(task: Arc<Task>, wake: Wake) {
+    let yielder = task.yielder.load(SeqCst);
+    drop(task);
+    // the yielder lives at the bottom of this task's stack for as long as it runs
+    unsafe { (*(yielder as *const Yielder<(), Wake>)).suspend(wake) }
+}
+
+// True when the running task has used up its slice and another task is
+// waiting for a worker. Read afresh each time, like task::current.
+#[inline(never)]
+pub fn slice_over() -> bool {
+    let Some(start) = SLICE_START.get() else {
+        return false;
+    };
+    if start.elapsed() < SLICE {
+        return false;
+    }
+    if pool().queue.lock().unwrap().is_empty() {
+        // nobody to make room for, check again after another slice
+        SLICE_START.set(Some(Instant::now()));
+        return false;
+    }
+    true
+}
+
+fn work() {
+    let pool = pool();
+    loop {
+        let task = {
+            let mut queue = pool.queue.lock().unwrap();
+            loop {
+                match queue.pop_front() {
+                    Some(t) => break t,
+                    None => queue = pool.ready.wait(queue).unwrap(),
+                }
+            }
+        };
+
+        {
+            let mut sched = task.sched.lock().unwrap();
+            sched.queued = false;
+            sched.running = true;
+            sched.notified = false;
+        }
+        let Some(mut fiber) = task.fiber.lock().unwrap().take() else {
+            continue;
+        };
+
+        task.enter();
+        SLICE_START.set(Some(Instant::now()));
+        let result = fiber.0.resume(());
+        SLICE_START.set(None);
+        Task::leave();
+
+        match result {
+            CoroutineResult::Yield(at) => {
+                *task.fiber.lock().unwrap() = Some(fiber);
+                let mut sched = task.sched.lock().unwrap();
+                sched.running = false;
+                if sched.notified {
+                    sched.queued = true;
+                    drop(sched);
+                    pool.queue.lock().unwrap().push_back(task);
+                    pool.ready.notify_one();
+                } else if let Some(at) = at {
+                    drop(sched);
+                    pool.timers
+                        .lock()
+                        .unwrap()
+                        .push(Reverse(Timer { at, task }));
+                    pool.timer_added.notify_one();
+                }
+            }
+            CoroutineResult::Return(()) => {
+                let mut sched = task.sched.lock().unwrap();
+                sched.running = false;
+                sched.done = true;
+            }
+        }
+    }
+}
+
+// wakes sleeping tasks when their time comes; a task woken earlier by
+// something else just gets a second, harmless wake
+fn fire_timers() {
+    let pool = pool();
+    let mut timers = pool.timers.lock().unwrap();
+    loop {
+        let now = Instant::now();
+        match timers.peek() {
+            Some(Reverse(t)) if t.at <= now => {
+                let Reverse(t) = timers.pop().unwrap();
+                drop(timers);
+                wake(&t.task);
+                timers = pool.timers.lock().unwrap();
+            }
+            Some(Reverse(t)) => {
+                let until = t.at - now;
+                timers = pool.timer_added.wait_timeout(timers, until).unwrap().0;
+            }
+            None => timers = pool.timer_added.wait(timers).unwrap(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::Value;
+    use crate::task;
+    use std::sync::atomic::AtomicBool;
+
+    // starts f as a task that ends with the number it gives back
+    fn start(f: impl FnOnce() -> i64 + Send + 'static) -> Arc<Task> {
+        let task = Task::new();
+        let t = task.clone();
+        task.start(move || t.finish(State::Done(Value::Num(f()))));
+        task
+    }
+
+    fn result(task: &Task) -> i64 {
+        match task.join() {
+            Some(State::Done(Value::Num(n))) => n,
+            s => panic!("task ended with {:?}", s),
+        }
+    }
+
+    #[test]
+    fn a_task_keeps_its_stack_across_waits() {
+        // it may be resumed by a different worker after every sleep
+        let task = start(|| {
+            let mut sum = 0;
+            for i in 0..20 {
+                sum += i;
+                task::sleep(Duration::from_millis(1));
+            }
+            sum
+        });
+        assert_eq!(result(&task), 190);
+    }
+
+    #[test]
+    fn busy_tasks_do_not_starve_the_rest() {
+        let stop = Arc::new(AtomicBool::new(false));
+        let busy: Vec<_> = (0..WORKERS * 2)
+            .map(|_| {
+                let stop = stop.clone();
+                start(move || {
+                    while !stop.load(SeqCst) {
+                        task::yield_point();
+                    }
+                    0
+                })
+            })
+            .collect();
+        let last = start(move || {
+            stop.store(true, SeqCst);
+            1
+        });
+        assert_eq!(result(&last), 1);
+        for t in busy {
+            assert_eq!(result(&t), 0);
+        }
+    }
+
+    #[test]
+    fn sleeping_tasks_wake_in_deadline_order() {
+        let woke = Arc::new(Mutex::new(Vec::new()));
+        let tasks: Vec<_> = [60, 20, 40]
+            .into_iter()
+            .map(|ms| {
+                let woke = woke.clone();
+                start(move || {
+                    task::sleep(Duration::from_millis(ms));
+                    woke.lock().unwrap().push(ms);
+                    0
+                })
+            })
+            .collect();
+        for t in tasks {
+            result(&t);
+        }
+        assert_eq!(*woke.lock().unwrap(), [20, 40, 60]);
+    }
+
+    #[test]
+    fn a_wake_while_running_is_not_lost() {
+        // woken before it waits, so the wait must return at once
+        let task = start(|| {
+            task::waker().wake();
+            task::wait(None);
+            1
+        });
+        assert_eq!(result(&task), 1);
+    }
+
+    #[test]
+    fn a_panic_ends_only_its_task() {
+        let task = Task::new();
+        task.start(|| panic!("a built-in went wrong"));
+        assert!(matches!(task.join(), Some(State::Failed(_))));
+        assert_eq!(result(&start(|| 2)), 2);
+    }
+
+    #[test]
+    fn many_tasks_share_the_workers() {
+        let tasks: Vec<_> = (0..1000)
+            .map(|i| {
+                start(move || {
+                    task::sleep(Duration::from_millis(5));
+                    i
+                })
+            })
+            .collect();
+        let sum: i64 = tasks.iter().map(|t| result(t)).sum();
+        assert_eq!(sum, 999 * 1000 / 2);
+    }
+}
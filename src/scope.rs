@@ -0,0 +1,81 @@
+use crate::interpreter::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+// One frame of the environment chain. Blocks, function calls and async tasks
+// each get their own frame; lookups and assignments walk up to the globals.
+// Each variable is stored with a flag saying whether it was declared `const`.
+#[derive(Debug)]
+pub struct Scope {
+    vars: Mutex<HashMap<String, (Value, bool)>>,
+    parent: Option<Arc<Scope>>,
+}
+
+impl Scope {
+    pub fn new(parent: Option<Arc<Scope>>) -> Arc<Self> {
+        Arc::new(Self {
+            vars: Mutex::new(HashMap::new()),
+            parent,
+        })
+    }
+
+    pub fn get(&self, name: &str) -> Option<Value> {
+        if let Some((v, _)) = self.vars.lock().unwrap().get(name) {
+            return Some(v.clone());
+        }
+        self.parent.as_ref()?.get(name)
+    }
+
+    pub fn declare(&self, name: &str, v: Value) {
+        self.vars
+            .lock()
+            .unwrap()
+            .insert(name.to_string(), (v, false));
+    }
+
+    pub fn declare_const(&self, name: &str, v: Value) {
+        self.vars
+            .lock()
+            .unwrap()
+            .insert(name.to_string(), (v, true));
+    }
+
+    // fails if no frame in the chain defines the variable or it is a constant
+    pub fn assign(&self, name: &str, v: Value) -> Result<(), String> {
+        let mut vars = self.vars.lock().unwrap();
+        if let Some((slot, constant)) = vars.get_mut(name) {
+            if *constant {
+                return Err(format!("cannot assign to constant '{}'", name));
+            }
+            *slot = v;
+            return Ok(());
+        }
+        drop(vars);
+        match &self.parent {
+            Some(p) => p.assign(name, v),
+            None => Err(format!("variable '{}' is not defined", name)),
+        }
+    }
+
+    // like assign, but the new value is made from the old one while the frame
+    // stays locked, so no other task can write in between
+    pub fn update<E: From<String>>(
+        &self,
+        name: &str,
+        f: impl FnOnce(Value) -> Result<Value, E>,
+    ) -> Result<(), E> {
+        let mut vars = self.vars.lock().unwrap();
+        if let Some((slot, constant)) = vars.get_mut(name) {
+            if *constant {
+                return Err(format!("cannot assign to constant '{}'", name).into());
+            }
+            *slot = f(slot.clone())?;
+            return Ok(());
+        }
+        drop(vars);
+        match &self.parent {
+            Some(p) => p.update(name, f),
+            None => Err(format!("variable '{}' is not defined", name).into()),
+        }
+    }
+}
@@ -0,0 +1,267 @@
+use crate::interpreter::{Error, Value};
+use crate::scheduler::{self, Fiber, Sched};
+use std::cell::{Cell, RefCell};
+use std::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, Weak};
+use std::thread::{self, Thread};
+use std::time::{Duration, Instant};
+
+// What `let t = async { ... }` gives back. Cancelling only sets a flag and
+// wakes the task; it notices before its next statement, or right away while
+// it sleeps or waits for a key, a value or another task.
+pub struct Task {
+    cancel: AtomicBool,
+    // set while a `finally` block runs after a cancel, so it can finish
+    shielded: AtomicBool,
+    state: Mutex<State>,
+    // tasks and threads in wait(), woken when the task ends
+    joiners: Mutex<Vec<Waker>>,
+    pub(crate) sched: Mutex<Sched>,
+    // the stack the task runs on, None while a worker is running it
+    pub(crate) fiber: Mutex<Option<Fiber>>,
+    pub(crate) yielder: AtomicPtr<()>,
+}
+
+#[derive(Clone, Debug)]
+pub enum State {
+    Running,
+    // the value of `return` in the block, false when it just ends
+    Done(Value),
+    // the error that ended it, located where it happened in the task
+    Failed(Error),
+    Cancelled,
+}
+
+// Whoever is waiting for something: a task, or the main thread when the
+// script itself waits.
+#[derive(Clone, Debug)]
+pub enum Waker {
+    Task(Arc<Task>),
+    Thread(Thread),
+}
+
+thread_local! {
+    // the task a worker is running right now, None on the main thread
+    static CURRENT: RefCell<Option<Arc<Task>>> = const { RefCell::new(None) };
+    // `shielded` for the main script, which never changes threads
+    static MAIN_SHIELDED: Cell<bool> = const { Cell::new(false) };
+}
+
+// Set once the whole script is stopping, by the kill switch, exit() or an
+// error. Every task and the main script then stop as if cancelled.
+static STOPPING: AtomicBool = AtomicBool::new(false);
+// the thread the main script runs on, and every task, so stop() can wake them
+static MAIN: OnceLock<Thread> = OnceLock::new();
+static TASKS: Mutex<Vec<Weak<Task>>> = Mutex::new(Vec::new());
+
+impl std::fmt::Debug for Task {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Task")
+            .field("state", &self.state())
+            .finish()
+    }
+}
+
+impl Task {
+    pub fn new() -> Arc<Self> {
+        let task = Arc::new(Self {
+            cancel: AtomicBool::new(false),
+            shielded: AtomicBool::new(false),
+            state: Mutex::new(State::Running),
+            joiners: Mutex::new(Vec::new()),
+            sched: Mutex::new(Sched::default()),
+            fiber: Mutex::new(None),
+            yielder: AtomicPtr::new(std::ptr::null_mut()),
+        });
+        let mut tasks = TASKS.lock().unwrap();
+        // forget ended tasks now and then rather than on every spawn
+        if tasks.len().is_power_of_two() {
+            tasks.retain(|t| t.strong_count() > 0);
+        }
+        tasks.push(Arc::downgrade(&task));
+        task
+    }
+
+    // runs f as this task on the worker pool
+    pub fn start(self: &Arc<Self>, f: impl FnOnce() + Send + 'static) {
+        scheduler::spawn(self, f);
+    }
+
+    // marks the worker thread as running the task, for `cancelled` and `wait`
+    pub(crate) fn enter(self: &Arc<Self>) {
+        CURRENT.with(|c| *c.borrow_mut() = Some(self.clone()));
+    }
+
+    pub(crate) fn leave() {
+        CURRENT.with(|c| *c.borrow_mut() = None);
+    }
+
+    pub fn finish(&self, state: State) {
+        *self.state.lock().unwrap() = state;
+        let joiners = std::mem::take(&mut *self.joiners.lock().unwrap());
+        for w in joiners {
+            w.wake();
+        }
+    }
+
+    pub fn cancel(self: &Arc<Self>) {
+        self.cancel.store(true, Ordering::SeqCst);
+        scheduler::wake(self);
+    }
+
+    fn is_cancelled(&self) -> bool {
+        (self.cancel.load(Ordering::SeqCst) || STOPPING.load(Ordering::SeqCst))
+            && !self.shielded.load(Ordering::SeqCst)
+    }
+
+    pub fn state(&self) -> State {
+        self.state.lock().unwrap().clone()
+    }
+
+    // waits until the task has ended; gives up early when the waiting task
+    // is cancelled itself and returns None
+    pub fn join(&self) -> Option<State> {
+        loop {
+            {
+                let state = self.state.lock().unwrap();
+                if !matches!(*state, State::Running) {
+                    return Some(state.clone());
+                }
+                if cancelled() {
+                    return None;
+                }
+                // registered before the lock is let go, so finish can't be missed
+                self.joiners.lock().unwrap().push(waker());
+            }
+            wait(None);
+        }
+    }
+}
+
+impl Waker {
+    pub fn wake(&self) {
+        match self {
+            Waker::Task(t) => scheduler::wake(t),
+            Waker::Thread(t) => t.unpark(),
+        }
+    }
+
+    // adds the waker to a list unless it is there already, a loop that keeps
+    // waking up for other reasons would fill it otherwise
+    pub fn add_to(self, list: &mut Vec<Waker>) {
+        let same = |w: &Waker| match (w, &self) {
+            (Waker::Task(a), Waker::Task(b)) => Arc::ptr_eq(a, b),
+            (Waker::Thread(a), Waker::Thread(b)) => a.id() == b.id(),
+            _ => false,
+        };
+        if !list.iter().any(same) {
+            list.push(self);
+        }
+    }
+}
+
+// A coroutine can move to another worker every time it waits, so the
+// thread-local must be read afresh each time rather than kept by the caller.
+#[inline(never)]
+pub fn current() -> Option<Arc<Task>> {
+    CURRENT.with(|c| c.borrow().clone())
+}
+
+// something that wakes whatever is running this code
+pub fn waker() -> Waker {
+    match current() {
+        Some(t) => Waker::Task(t),
+        None => Waker::Thread(thread::current()),
+    }
+}
+
+// Gives up the worker until woken or the deadline passes; the main thread
+// parks instead. It can also return early, so callers check again in a loop.
+pub fn wait(deadline: Option<Instant>) {
+    match current() {
+        Some(t) => scheduler::suspend(t, deadline),
+        None => match deadline {
+            Some(d) => thread::park_timeout(d.saturating_duration_since(Instant::now())),
+            None => thread::park(),
+        },
+    }
+}
+
+// Called at every turn of a loop. A task that has been busy for a while goes
+// to the back of the line when others are ready, so a few tasks spinning in
+// loops can't take every worker; the main script has a thread of its own.
+pub fn yield_point() {
+    if scheduler::slice_over()
+        && let Some(t) = current()
+    {
+        scheduler::wake(&t);
+        scheduler::suspend(t, None);
+    }
+}
+
+// true once the task running this code has been cancelled, or for the
+// main script once the script is stopping
+pub fn cancelled() -> bool {
+    match current() {
+        Some(t) => t.is_cancelled(),
+        None => STOPPING.load(Ordering::SeqCst) && !MAIN_SHIELDED.get(),
+    }
+}
+
+// runs f as if the task had not been cancelled
+pub fn shielded<T>(f: impl FnOnce() -> T) -> T {
+    let Some(t) = current() else {
+        let outer = MAIN_SHIELDED.replace(true);
+        let v = f();
+        MAIN_SHIELDED.set(outer);
+        return v;
+    };
+    let outer = t.shielded.swap(true, Ordering::SeqCst);
+    let v = f();
+    t.shielded.store(outer, Ordering::SeqCst);
+    v
+}
+
+// called by the main script's thread before it starts
+pub fn set_main(thread: Thread) {
+    let _ = MAIN.set(thread);
+}
+
+// Cancels every task and the main script; whatever they wait for, they
+// stop waiting. Their finally blocks still run.
+pub fn stop() {
+    STOPPING.store(true, Ordering::SeqCst);
+    let tasks: Vec<Arc<Task>> = TASKS
+        .lock()
+        .unwrap()
+        .iter()
+        .filter_map(Weak::upgrade)
+        .collect();
+    for t in tasks {
+        scheduler::wake(&t);
+    }
+    if let Some(main) = MAIN.get() {
+        main.unpark();
+    }
+}
+
+pub fn stopping() -> bool {
+    STOPPING.load(Ordering::SeqCst)
+}
+
+// Sleeps without holding up a worker, and wakes up as soon as the task is
+// cancelled.
+pub fn sleep(duration: Duration) {
+    if duration.is_zero() {
+        return;
+    }
+    let deadline = deadline(duration);
+    while !cancelled() && deadline.is_none_or(|d| Instant::now() < d) {
+        wait(deadline);
+    }
+}
+
+// when a wait of this long ends, None for one so long it never does
+pub fn deadline(duration: Duration) -> Option<Instant> {
+    Instant::now().checked_add(duration)
+}
@@ -1,33 +1,64 @@
 #[derive(Clone, Debug, PartialEq)]
 pub enum TokenKind {
     Var,
+    Const,
     Def,
+    Fn,
     Return,
     If,
     Elif,
     Else,
     Loop,
     While,
+    For,
+    In,
     Async,
     Await,
+    Try,
+    Catch,
+    Match,
+    Select,
+    Lock,
+    Finally,
     Break,
     Continue,
+    Import,
+    Struct,
     True,
     False,
     Ident(String),
     Number(i64),
+    Float(f64),
     Str(String),
+    // a string with {placeholders}, already split into text and code
+    Template(Vec<TemplatePart>),
     LParen,
     RParen,
     LBrace,
     RBrace,
+    LBracket,
+    RBracket,
     Comma,
+    Colon,
+    Question,
     Semicolon,
     Dot,
+    DotDot,
+    DotDotEq,
+    FatArrow,
     Plus,
     Minus,
     Star,
     Slash,
+    Percent,
+    StarStar,
+    PlusEq,
+    MinusEq,
+    StarEq,
+    SlashEq,
+    PercentEq,
+    PlusPlus,
+    MinusMinus,
     Eq,
     EqEq,
     Ne,
@@ -38,10 +69,97 @@ pub enum TokenKind {
     AndAnd,
     OrOr,
     Bang,
+    // input the lexer could not make sense of, already reported by it
+    Error,
     Eof,
 }
 
-#[derive(Clone, Debug)]
+impl TokenKind {
+    // how the token reads in error messages
+    pub fn describe(&self) -> String {
+        let text = match self {
+            TokenKind::Ident(s) => return format!("identifier `{}`", s),
+            TokenKind::Number(n) => return format!("number `{}`", n),
+            TokenKind::Float(n) => return format!("number `{}`", n),
+            TokenKind::Str(_) | TokenKind::Template(_) => return "string".into(),
+            TokenKind::Semicolon => return "end of statement".into(),
+            TokenKind::Eof => return "end of file".into(),
+            TokenKind::Error => return "invalid character".into(),
+            TokenKind::Var => "let",
+            TokenKind::Const => "const",
+            TokenKind::Import => "import",
+            TokenKind::Struct => "struct",
+            TokenKind::Def => "def",
+            TokenKind::Fn => "fn",
+            TokenKind::Return => "return",
+            TokenKind::If => "if",
+            TokenKind::Elif => "elif",
+            TokenKind::Else => "else",
+            TokenKind::Loop => "loop",
+            TokenKind::While => "while",
+            TokenKind::For => "for",
+            TokenKind::In => "in",
+            TokenKind::Async => "async",
+            TokenKind::Await => "await",
+            TokenKind::Try => "try",
+            TokenKind::Catch => "catch",
+            TokenKind::Match => "match",
+            TokenKind::Select => "select",
+            TokenKind::Lock => "lock",
+            TokenKind::Finally => "finally",
+            TokenKind::Break => "break",
+            TokenKind::Continue => "continue",
+            TokenKind::True => "true",
+            TokenKind::False => "false",
+            TokenKind::LParen => "(",
+            TokenKind::RParen => ")",
+            TokenKind::LBrace => "{",
+            TokenKind::RBrace => "}",
+            TokenKind::LBracket => "[",
+            TokenKind::RBracket => "]",
+            TokenKind::Comma => ",",
+            TokenKind::Colon => ":",
+            TokenKind::Question => "?",
+            TokenKind::Dot => ".",
+            TokenKind::DotDot => "..",
+            TokenKind::DotDotEq => "..=",
+            TokenKind::FatArrow => "=>",
+            TokenKind::Plus => "+",
+            TokenKind::Minus => "-",
+            TokenKind::Star => "*",
+            TokenKind::Slash => "/",
+            TokenKind::Percent => "%",
+            TokenKind::StarStar => "**",
+            TokenKind::PlusEq => "+=",
+            TokenKind::MinusEq => "-=",
+            TokenKind::StarEq => "*=",
+            TokenKind::SlashEq => "/=",
+            TokenKind::PercentEq => "%=",
+            TokenKind::PlusPlus => "++",
+            TokenKind::MinusMinus => "--",
+            TokenKind::Eq => "=",
+            TokenKind::EqEq => "==",
+            TokenKind::Ne => "!=",
+            TokenKind::Gt => ">",
+            TokenKind::Lt => "<",
+            TokenKind::Ge => ">=",
+            TokenKind::Le => "<=",
+            TokenKind::AndAnd => "&&",
+            TokenKind::OrOr => "||",
+            TokenKind::Bang => "!",
+        };
+        format!("`{}`", text)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TemplatePart {
+    Text(String),
+    // the tokens inside the braces, ending with the closing `}`
+    Code(Vec<Token>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct Token {
     pub kind: TokenKind,
     pub line: usize,